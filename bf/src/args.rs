@@ -1,4 +1,10 @@
 use clap::{ArgAction, Parser};
+use compiler::{Backend, Emit, Target};
+use interpreter::{
+    config::{Boundary, CellWidth, Engine, EofBehavior, Overflow, TextMode},
+    lint::Lint,
+    optimize::Pass,
+};
 use std::fmt;
 
 #[derive(Parser)]
@@ -12,11 +18,75 @@ pub(crate) struct Args {
     #[clap(action=ArgAction::SetTrue, conflicts_with = "interpret")]
     pub compile: bool,
 
+    #[arg(
+        long,
+        value_name = "ENGINE",
+        default_value = "vm",
+        help = "Interpreter to run with -i: vm, legacy or jit"
+    )]
+    pub engine: Engine,
+
+    #[arg(long, help = "Run with the x86-64 JIT, same as --engine jit")]
+    #[clap(action=ArgAction::SetTrue, conflicts_with = "engine")]
+    pub jit: bool,
+
+    #[arg(
+        long,
+        value_name = "BACKEND",
+        default_value = "llvm",
+        help = "Code generator to use with -c: llvm or elf"
+    )]
+    pub backend: Backend,
+
+    #[arg(
+        long,
+        value_name = "LANGUAGE",
+        help = "Translate to source code instead of an executable with -c: c, rust, asm, wasm or qbe"
+    )]
+    pub emit: Option<Emit>,
+
+    #[arg(short = 'l', long, help = "Check input for likely mistakes")]
+    #[clap(action=ArgAction::SetTrue, conflicts_with_all = ["interpret", "compile"])]
+    pub lint: bool,
+
+    #[arg(short = 'A', long, value_name = "LINT", help = "Do not report a lint")]
+    pub allow: Vec<Lint>,
+
+    #[arg(
+        short = 'W',
+        long,
+        value_name = "LINT",
+        help = "Report a lint as a warning"
+    )]
+    pub warn: Vec<Lint>,
+
+    #[arg(
+        short = 'D',
+        long,
+        value_name = "LINT",
+        help = "Report a lint as an error"
+    )]
+    pub deny: Vec<Lint>,
+
     #[arg(short = 'O', long, help = "Optimization level (0-2)")]
-    #[clap(default_value_t = 0)]
+    #[clap(default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=2))]
     pub optimizatoin: u32,
 
-    #[arg(long, help = "Print the generated LLVM IR")]
+    #[arg(
+        long = "pass",
+        value_name = "PASS",
+        help = "Enable an optimization pass"
+    )]
+    pub passes: Vec<Pass>,
+
+    #[arg(
+        long = "no-pass",
+        value_name = "PASS",
+        help = "Disable an optimization pass"
+    )]
+    pub no_passes: Vec<Pass>,
+
+    #[arg(long, help = "Print the generated LLVM IR, after --llvm-opt if given")]
     #[clap(action=ArgAction::SetTrue)]
     pub dump_llvm: bool,
 
@@ -24,19 +94,84 @@ pub(crate) struct Args {
     #[clap(action=ArgAction::SetTrue)]
     pub dump_ir: bool,
 
-    #[arg(long, help = "LLVM Optimization level (0-3)")]
-    #[clap(default_value_t = 0)]
+    #[arg(
+        long,
+        help = "Run LLVM's optimizer on the generated IR at this level (0-3)"
+    )]
+    #[clap(default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=3))]
     pub llvm_opt: u32,
 
-    #[arg(short = 'g', long, help = "Include debugging information")]
+    #[arg(
+        short = 'g',
+        long,
+        help = "Include DWARF line information and the tape for debuggers with -c"
+    )]
     #[clap(action=ArgAction::SetTrue)]
     pub debug: bool,
 
-    #[arg(short = 't', long, help = "LLVM target triple")]
-    pub target: Option<String>,
+    #[arg(
+        short = 't',
+        long,
+        value_name = "TRIPLE",
+        help = "Target triple to compile for, e.g. aarch64-unknown-linux-gnu; defaults to the host"
+    )]
+    pub target: Option<Target>,
+
+    #[arg(long, help = "Stop interpreting after this many steps")]
+    pub step_limit: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "ENCODING",
+        help = "Decode output as utf8 or latin1 text instead of raw bytes"
+    )]
+    pub text: Option<TextMode>,
+
+    #[arg(
+        long,
+        value_name = "BEHAVIOR",
+        default_value = "unchanged",
+        help = "What , stores at end of input: unchanged, zero or minus-one"
+    )]
+    pub eof: EofBehavior,
+
+    #[arg(
+        long = "cell-bits",
+        value_name = "BITS",
+        default_value = "8",
+        help = "Width of a tape cell: 8, 16, 32 or 64"
+    )]
+    pub cell_width: CellWidth,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        default_value = "wrap",
+        help = "What + and - do at the cell limits: wrap, saturate or error"
+    )]
+    pub overflow: Overflow,
+
+    #[arg(long, value_name = "CELLS", help = "Number of cells on the tape")]
+    #[clap(default_value_t = 30000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub tape_size: u64,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        default_value = "error",
+        help = "What moving off the tape does: error, wrap, grow or unbounded"
+    )]
+    pub tape_boundary: Boundary,
+
+    #[arg(long, help = "Print execution statistics to stderr")]
+    #[clap(action=ArgAction::SetTrue)]
+    pub stats: bool,
 
     #[arg(short = 'f', long, help = "BF input file")]
     pub file: String,
+
+    #[arg(short = 'o', long, help = "Output executable path")]
+    pub output: Option<String>,
 }
 
 impl fmt::Display for Args {
@@ -45,23 +180,61 @@ impl fmt::Display for Args {
             "
         Interpret:     {}
         Compile:       {}
+        Engine:        {:?}
+        JIT:           {}
+        Backend:       {:?}
+        Emit:          {:?}
+        Lint:          {}
+        Allow:         {:?}
+        Warn:          {:?}
+        Deny:          {:?}
         Optimization:  {}
+        Passes:        {:?}
+        No_Passes:     {:?}
         Dump_LLVM:     {}
         DUMP_IR:       {}
         LLVM_Opt:      {}
         Debug:         {}
         Target:        {:?}
+        Step_Limit:    {:?}
+        Text:          {:?}
+        EOF:           {:?}
+        Cell_Width:    {:?}
+        Overflow:      {:?}
+        Tape_Size:     {}
+        Tape_Boundary: {:?}
+        Stats:         {}
         File:          {}
+        Output:        {:?}
 ",
             self.interpret,
             self.compile,
+            self.engine,
+            self.jit,
+            self.backend,
+            self.emit,
+            self.lint,
+            self.allow,
+            self.warn,
+            self.deny,
             self.optimizatoin,
+            self.passes,
+            self.no_passes,
             self.dump_llvm,
             self.dump_ir,
             self.llvm_opt,
             self.debug,
             self.target,
+            self.step_limit,
+            self.text,
+            self.eof,
+            self.cell_width,
+            self.overflow,
+            self.tape_size,
+            self.tape_boundary,
+            self.stats,
             self.file,
+            self.output,
         );
         write!(f, "{}", string)
     }
@@ -1,6 +1,18 @@
-use std::{io, path::Path};
+use std::{fs, io, path::Path};
 mod args;
-use interpreter::{interpret, read_program};
+use compiler::{
+    emit_asm, emit_c, emit_elf, emit_llvm, emit_qbe, emit_rust, emit_wasm, with_suffix, Backend,
+    Emit, Optimizer, Target, Toolchain,
+};
+use interpreter::{
+    config::{Config, Engine, TapeConfig},
+    error::{annotate, snippet},
+    execute, execute_jit, interpret,
+    ir::Module,
+    lint::{lint, Levels, Severity},
+    optimize::{optimize, Passes},
+    read_program,
+};
 
 fn main() -> anyhow::Result<()> {
     let args = args::parse();
@@ -9,19 +21,242 @@ fn main() -> anyhow::Result<()> {
         println!("Please specify a BF file.");
         std::process::exit(2);
     }
-    if !args.interpret && !args.compile {
-        println!("Please select either interpret mode or compile mode.");
+    if !args.interpret && !args.compile && !args.lint {
+        println!("Please select interpret, compile or lint mode.");
         std::process::exit(3);
     }
 
     let path = Path::new(&args.file);
-    if args.interpret {
-        let content = read_program(path)?;
+    let content = read_program(path)?;
+    let mut program = match Module::parse(&content) {
+        Ok(program) => program,
+        Err(err) => {
+            eprint!("{}", err.snippet(&content));
+            std::process::exit(4);
+        }
+    };
+
+    if args.lint {
+        let mut levels = Levels::default();
+        for (lints, severity) in [
+            (&args.allow, Severity::Allow),
+            (&args.warn, Severity::Warn),
+            (&args.deny, Severity::Error),
+        ] {
+            for lint in lints {
+                levels.set(*lint, severity);
+            }
+        }
+        let findings = lint(&program, &levels);
+        for finding in &findings {
+            eprint!(
+                "{}",
+                annotate(
+                    &content,
+                    finding.position,
+                    &finding.label(),
+                    &finding.message
+                )
+            );
+        }
+        if findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+        {
+            std::process::exit(6);
+        }
+        return Ok(());
+    }
 
+    let config = Config {
+        step_limit: args.step_limit,
+        text_mode: args.text,
+        eof: args.eof,
+        cell_width: args.cell_width,
+        overflow: args.overflow,
+        tape: TapeConfig {
+            length: args.tape_size as usize,
+            boundary: args.tape_boundary,
+        },
+    };
+    let mut passes = Passes::level(args.optimizatoin);
+    for pass in &args.passes {
+        passes.enable(*pass);
+    }
+    for pass in &args.no_passes {
+        passes.disable(*pass);
+    }
+    optimize(&mut program, &passes, &config);
+    if args.dump_ir {
+        print!("{}", program);
+    }
+
+    // Debug information refers to the source by its absolute path.
+    let debug = if args.compile && args.debug {
+        Some(fs::canonicalize(path)?)
+    } else {
+        None
+    };
+
+    if args.interpret {
+        let mut stdin = io::stdin().lock();
         let mut stdout = io::stdout().lock();
-        interpret(content, &mut stdout);
+        let engine = if args.jit { Engine::Jit } else { args.engine };
+        let result = match engine {
+            Engine::Vm => execute(&program, &mut stdin, &mut stdout, &config),
+            Engine::Legacy => interpret(content.clone(), &mut stdin, &mut stdout, &config),
+            Engine::Jit => execute_jit(&program, &mut stdin, &mut stdout, &config),
+        };
+        match result {
+            Ok(stats) => {
+                if args.stats {
+                    eprintln!(
+                        "steps: {}, input bytes: {}, output bytes: {}, peak tape memory: {} bytes",
+                        stats.steps, stats.input_bytes, stats.output_bytes, stats.tape_bytes
+                    );
+                }
+            }
+            Err(err) => {
+                match err.position() {
+                    Some(position) => eprint!("{}", snippet(&content, position, &err.to_string())),
+                    None => eprintln!("error: {}", err),
+                }
+                std::process::exit(5);
+            }
+        }
+    } else if let (true, Some(emit)) = (args.compile, args.emit) {
+        let module = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut output = match &args.output {
+            Some(output) => Path::new(output).to_path_buf(),
+            None => path.with_extension(emit.extension()),
+        };
+        match emit {
+            Emit::C => fs::write(&output, emit_c(&program, &module, &config)?)?,
+            Emit::Rust => fs::write(&output, emit_rust(&program, &module, &config)?)?,
+            Emit::Asm => fs::write(
+                &output,
+                emit_asm(
+                    &program,
+                    &content,
+                    &module,
+                    &config,
+                    target(&args).as_ref(),
+                    debug.as_deref(),
+                )?,
+            )?,
+            Emit::Qbe => fs::write(&output, emit_qbe(&program, &module, &config)?)?,
+            Emit::Wasm => {
+                let wasm = emit_wasm(&program, &module, &config)?;
+                let text = output.with_extension("wat");
+                if text == output {
+                    // `-o out.wat` names the text; the binary goes beside it.
+                    output = output.with_extension("wasm");
+                }
+                fs::write(&text, wasm.text)?;
+                eprintln!("Wrote {}", text.display());
+                fs::write(&output, wasm.binary)?;
+            }
+        }
+        eprintln!("Wrote {}", output.display());
     } else if args.compile {
-        todo!()
+        let output = match &args.output {
+            Some(output) => Path::new(output).to_path_buf(),
+            None if path.extension().is_some() => path.with_extension(""),
+            None => with_suffix(path, "out"),
+        };
+        match args.backend {
+            Backend::Llvm => {
+                let module = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let toolchain = Toolchain::detect();
+                let optimizer = if args.llvm_opt > 0 {
+                    Optimizer::detect()
+                } else {
+                    None
+                };
+                let majors = toolchain.iter().map(Toolchain::major);
+                let typed_pointers = majors
+                    .chain(optimizer.iter().map(Optimizer::major))
+                    .any(|major| major < 15);
+                let mut ir = emit_llvm(
+                    &program,
+                    &module,
+                    &config,
+                    target(&args).as_ref(),
+                    debug.as_deref(),
+                    typed_pointers,
+                )?;
+                let mut ir_path = with_suffix(&output, "ll");
+                fs::write(&ir_path, &ir)?;
+                let mut intermediates = vec![ir_path.clone()];
+
+                if args.llvm_opt > 0 {
+                    match optimizer {
+                        Some(optimizer) => {
+                            let optimized = with_suffix(&output, "opt.ll");
+                            let passes = optimizer.optimize(&ir_path, &optimized, args.llvm_opt)?;
+                            eprintln!(
+                                "{} -O{} ran {} passes: {}",
+                                optimizer.name(),
+                                args.llvm_opt,
+                                passes.len(),
+                                passes.join(", ")
+                            );
+                            ir = fs::read_to_string(&optimized)?;
+                            intermediates.push(optimized.clone());
+                            ir_path = optimized;
+                        }
+                        None => eprintln!(
+                            "Ignoring --llvm-opt; install opt or clang to run LLVM's optimizer."
+                        ),
+                    }
+                }
+                if args.dump_llvm {
+                    print!("{}", ir);
+                }
+
+                match toolchain {
+                    Some(toolchain) => {
+                        toolchain.build(&ir_path, &output, args.target.as_ref())?;
+                        for path in intermediates {
+                            fs::remove_file(path)?;
+                        }
+                        eprintln!("Compiled {} with {}", output.display(), toolchain.name());
+                    }
+                    None => eprintln!(
+                        "Wrote {}; install clang or llc to build an executable.",
+                        ir_path.display()
+                    ),
+                }
+            }
+            Backend::Elf => {
+                let executable =
+                    emit_elf(&program, &config, target(&args).as_ref(), debug.as_deref())?;
+                write_executable(&output, &executable)?;
+                eprintln!("Compiled {} with the ELF writer", output.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The target given with `--target`, or the host if it is one bf knows.
+fn target(args: &args::Args) -> Option<Target> {
+    args.target.clone().or_else(|| Target::host().ok())
+}
+
+/// Writes `content` to `path` and marks it executable.
+fn write_executable(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    fs::write(path, content)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
     }
     Ok(())
 }
@@ -0,0 +1,677 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::Context;
+use interpreter::{
+    config::{Boundary, CellWidth, Config, EofBehavior},
+    ir::{commands, Module, Position},
+};
+
+use crate::{
+    dwarf,
+    lower::{check, lower, Lower, OFF_TAPE_MESSAGE, OFF_TAPE_STATUS},
+    target::Target,
+};
+
+/// The longest BF fragment shown in a comment.
+const FRAGMENT_LENGTH: usize = 60;
+
+const SUBROUTINES: &str = "
+# Writes the low byte of the current cell to stdout.
+write_cell:
+\tmovl\t$1, %eax
+\tmovl\t$1, %edi
+\tmovq\t%r12, %rsi
+\tmovl\t$1, %edx
+\tsyscall
+\tret
+
+# Reads one byte from stdin into input_byte, returning the count in %rax.
+read_byte:
+\txorl\t%eax, %eax
+\txorl\t%edi, %edi
+\tleaq\tinput_byte(%rip), %rsi
+\tmovl\t$1, %edx
+\tsyscall
+\tret
+";
+
+/// Emits AT&T syntax that keeps the current cell in `%r12`, with a wrapping
+/// tape the tape end in `%r13`, and otherwise the tape start in `%rbx`.
+struct Emitter {
+    eof: EofBehavior,
+    width: CellWidth,
+    /// The size of a cell in bytes.
+    size: i64,
+    /// The tape length, if moves wrap around it.
+    wrap: Option<i64>,
+    /// The tape length, if moves off the tape are errors.
+    bound: Option<i64>,
+    /// Whether any check jumps to `off_tape`.
+    off_tape: bool,
+    /// Every BF command in the source, for showing fragments.
+    commands: Vec<(char, Position)>,
+    text: String,
+    /// Whether to emit `.loc` directives.
+    debug: bool,
+    /// The position of the operation being lowered.
+    position: Position,
+    /// Whether the next operation starts a straight run.
+    run_pending: bool,
+    /// The offset in `text` and the first command of the current straight
+    /// run, whose fragment is inserted when the run ends.
+    run: Option<(usize, usize)>,
+    /// The label suffix and closing command of each open loop.
+    loops: Vec<(String, usize)>,
+    /// The label suffix of the current run of multiplies.
+    multiplies: String,
+    /// The first error, such as a move too large to encode.
+    error: Option<anyhow::Error>,
+}
+
+impl Emitter {
+    fn line(&mut self, instruction: &str, operands: &str) {
+        if operands.is_empty() {
+            writeln!(self.text, "\t{}", instruction).unwrap();
+        } else {
+            writeln!(self.text, "\t{}\t{}", instruction, operands).unwrap();
+        }
+    }
+
+    fn label(&mut self, label: &str, comment: &str) {
+        writeln!(self.text, "{}:\t\t\t\t# {}", label, comment).unwrap();
+    }
+
+    /// Attributes the following instructions to `position`, with `-g`.
+    fn loc(&mut self, position: Position) {
+        if self.debug {
+            self.line(".loc", &format!("1 {} {}", position.line, position.column));
+        }
+    }
+
+    /// The instruction suffix for a cell.
+    fn suffix(&self) -> char {
+        match self.width {
+            CellWidth::Bits8 => 'b',
+            CellWidth::Bits16 => 'w',
+            CellWidth::Bits32 => 'l',
+            CellWidth::Bits64 => 'q',
+        }
+    }
+
+    /// The part of `%rax` that holds a cell.
+    fn register(&self) -> &'static str {
+        match self.width {
+            CellWidth::Bits8 => "%al",
+            CellWidth::Bits16 => "%ax",
+            CellWidth::Bits32 => "%eax",
+            CellWidth::Bits64 => "%rax",
+        }
+    }
+
+    /// The index of the command at `position`.
+    fn command(&self, position: Position) -> usize {
+        self.commands
+            .binary_search_by_key(&(position.line, position.column), |(_, position)| {
+                (position.line, position.column)
+            })
+            .unwrap_or_else(|index| index)
+    }
+
+    /// The index of the `]` matching the `[` at `open`.
+    fn closing(&self, open: usize) -> usize {
+        let mut depth = 0;
+        for (index, (command, _)) in self.commands.iter().enumerate().skip(open) {
+            match command {
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return index;
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.commands.len()
+    }
+
+    /// The source of the commands from `start` up to `end`, shortened if it
+    /// is long.
+    fn fragment(&self, start: usize, end: usize) -> String {
+        let end = end.max(start);
+        let mut fragment: String = self.commands[start..end]
+            .iter()
+            .take(FRAGMENT_LENGTH)
+            .map(|(command, _)| command)
+            .collect();
+        if end - start > FRAGMENT_LENGTH {
+            fragment.push_str("...");
+        }
+        fragment
+    }
+
+    /// The label suffix for the current operation, after its line and
+    /// column.
+    fn name(&self) -> String {
+        format!("{}_{}", self.position.line, self.position.column)
+    }
+
+    /// Ends the current straight run before the command at `end`, inserting
+    /// a comment with its BF before its instructions.
+    fn end_run(&mut self, end: usize) {
+        if let Some((offset, start)) = self.run.take() {
+            if start < end {
+                let comment = format!("\t# {}\n", self.fragment(start, end));
+                self.text.insert_str(offset, &comment);
+            }
+        }
+        self.run_pending = true;
+    }
+
+    /// Scales `offset` cells to bytes.
+    fn bytes(&mut self, offset: isize) -> i32 {
+        let bytes = i32::try_from(offset as i64 * self.size)
+            .context("moves must span less than 2 GiB of tape");
+        bytes.unwrap_or_else(|err| {
+            self.error.get_or_insert(err);
+            0
+        })
+    }
+
+    /// Wraps the address in `register` back onto the tape.
+    fn wrap_address(&mut self, register: &str) {
+        if let Some(length) = self.wrap {
+            self.line("cmpq", &format!("%r13, {}", register));
+            self.line("jb", "1f");
+            self.line("subq", &format!("${}, {}", length * self.size, register));
+            writeln!(self.text, "1:").unwrap();
+        }
+    }
+
+    /// Jumps to `off_tape` unless the address in `register` is on the tape.
+    /// Addresses left of the tape become large unsigned indices.
+    fn check_bounds(&mut self, register: &str) {
+        if let Some(length) = self.bound {
+            self.line("movq", &format!("{}, %rdx", register));
+            self.line("subq", "%rbx, %rdx");
+            self.line("cmpq", &format!("${}, %rdx", length * self.size));
+            self.line("jae", "off_tape");
+            self.off_tape = true;
+        }
+    }
+
+    fn compare_zero(&mut self) {
+        let instruction = format!("cmp{}", self.suffix());
+        self.line(&instruction, "$0, (%r12)");
+    }
+
+    /// Loads the current cell, zero-extended, into `%rax`.
+    fn load(&mut self) {
+        match self.width {
+            CellWidth::Bits8 => self.line("movzbl", "(%r12), %eax"),
+            CellWidth::Bits16 => self.line("movzwl", "(%r12), %eax"),
+            CellWidth::Bits32 => self.line("movl", "(%r12), %eax"),
+            CellWidth::Bits64 => self.line("movq", "(%r12), %rax"),
+        }
+    }
+}
+
+impl Lower for Emitter {
+    fn locate(&mut self, position: Position) {
+        if self.run_pending {
+            self.run = Some((self.text.len(), self.command(position)));
+            self.run_pending = false;
+        }
+        self.position = position;
+        self.loc(position);
+    }
+
+    fn add(&mut self, amount: i64) {
+        if i32::try_from(amount).is_ok() {
+            let instruction = format!("add{}", self.suffix());
+            self.line(&instruction, &format!("${}, (%r12)", amount));
+        } else {
+            self.line("movabsq", &format!("${}, %rax", amount));
+            self.line("addq", "%rax, (%r12)");
+        }
+    }
+
+    fn shift(&mut self, offset: isize) {
+        let bytes = self.bytes(offset);
+        if bytes < 0 {
+            self.line("subq", &format!("${}, %r12", -(bytes as i64)));
+        } else {
+            self.line("addq", &format!("${}, %r12", bytes));
+        }
+        self.wrap_address("%r12");
+        self.check_bounds("%r12");
+    }
+
+    fn output(&mut self) {
+        self.line("call", "write_cell");
+    }
+
+    fn input(&mut self) {
+        let name = self.name();
+        self.line("call", "read_byte");
+        self.line("testq", "%rax, %rax");
+        let on_eof = match self.eof {
+            EofBehavior::Unchanged => None,
+            EofBehavior::Zero => Some(0),
+            EofBehavior::MinusOne => Some(-1),
+        };
+        if on_eof.is_some() {
+            self.line("jle", &format!(".Leof_{}", name));
+        } else {
+            self.line("jle", &format!(".Lread_{}", name));
+        }
+        self.line("movzbl", "input_byte(%rip), %eax");
+        let instruction = format!("mov{}", self.suffix());
+        let register = self.register();
+        self.line(&instruction, &format!("{}, (%r12)", register));
+        if let Some(value) = on_eof {
+            self.line("jmp", &format!(".Lread_{}", name));
+            self.label(&format!(".Leof_{}", name), "end of input");
+            self.line(&instruction, &format!("${}, (%r12)", value));
+        }
+        self.label(&format!(".Lread_{}", name), "read done");
+    }
+
+    fn clear(&mut self) {
+        let instruction = format!("mov{}", self.suffix());
+        self.line(&instruction, "$0, (%r12)");
+    }
+
+    fn loop_start(&mut self, _: usize, position: Position) {
+        let open = self.command(position);
+        self.end_run(open);
+        let name = self.name();
+        self.compare_zero();
+        self.line("je", &format!(".Lend_{}", name));
+        self.label(
+            &format!(".Lloop_{}", name),
+            &format!("[ at {}:{}", position.line, position.column),
+        );
+        let close = self.closing(open);
+        self.loops.push((name, close));
+    }
+
+    fn loop_end(&mut self, _: usize) {
+        let (name, close) = self.loops.pop().expect("loop_end follows loop_start");
+        self.end_run(close);
+        let position = self
+            .commands
+            .get(close)
+            .map_or(self.position, |(_, position)| *position);
+        self.loc(position);
+        self.compare_zero();
+        self.line("jne", &format!(".Lloop_{}", name));
+        self.label(
+            &format!(".Lend_{}", name),
+            &format!("] at {}:{}", position.line, position.column),
+        );
+    }
+
+    fn scan(&mut self, _: usize, _: Position, stride: isize) {
+        let name = self.name();
+        self.label(&format!(".Lscan_{}", name), "scan");
+        self.compare_zero();
+        self.line("je", &format!(".Lscan_end_{}", name));
+        self.shift(stride);
+        self.line("jmp", &format!(".Lscan_{}", name));
+        self.label(&format!(".Lscan_end_{}", name), "scan done");
+    }
+
+    fn mul_start(&mut self, _: usize) {
+        let name = self.name();
+        self.compare_zero();
+        self.line("je", &format!(".Lmul_{}", name));
+        self.multiplies = name;
+    }
+
+    fn mul(&mut self, offset: isize, factor: i64) {
+        self.load();
+        if factor == 1 {
+            // The cell is added as it is.
+        } else if i32::try_from(factor).is_ok() {
+            self.line("imulq", &format!("${}, %rax, %rax", factor));
+        } else {
+            self.line("movabsq", &format!("${}, %rdx", factor));
+            self.line("imulq", "%rdx, %rax");
+        }
+        let bytes = self.bytes(offset);
+        let target = if self.wrap.is_some() || self.bound.is_some() {
+            self.line("leaq", &format!("{}(%r12), %rcx", bytes));
+            self.wrap_address("%rcx");
+            self.check_bounds("%rcx");
+            "(%rcx)".to_string()
+        } else {
+            format!("{}(%r12)", bytes)
+        };
+        let instruction = format!("add{}", self.suffix());
+        let register = self.register();
+        self.line(&instruction, &format!("{}, {}", register, target));
+    }
+
+    fn mul_end(&mut self, _: usize) {
+        let label = format!(".Lmul_{}", self.multiplies);
+        self.label(&label, "multiply done");
+    }
+}
+
+/// Lowers a BF module to x86-64 GNU assembler source in AT&T syntax.
+///
+/// The program defines `_start`, talks to the kernel through the `read`,
+/// `write` and `exit` syscalls and keeps the tape in `.bss`, so it assembles
+/// with `as` and links with `ld` alone. Loops are labelled `.Lloop_L_C`
+/// after the line and column of their `[`, and each straight run of
+/// operations is preceded by a comment with the BF it was compiled from.
+/// Unless the tape wraps, every move is checked, and one off the tape jumps
+/// to `off_tape`, which writes a message to stderr and exits with status 5.
+///
+/// `target`, if known, must be x86-64 Linux.
+///
+/// With `debug`, the path of the source file, each operation is preceded by
+/// a `.loc` directive and the output describes `_start` and the `tape` array
+/// in DWARF, so `as` builds the line table.
+pub fn emit_asm(
+    program: &Module,
+    source: &[char],
+    module: &str,
+    config: &Config,
+    target: Option<&Target>,
+    debug: Option<&Path>,
+) -> anyhow::Result<String> {
+    if let Some(target) = target {
+        target.require_x86_64_linux("--emit asm")?;
+    }
+    check(config)?;
+    let size = config.cell_width.bits() as i64 / 8;
+    let length = config.tape.length as i64;
+    let wrap = (config.tape.boundary == Boundary::Wrap).then_some(length);
+    let mut emitter = Emitter {
+        eof: config.eof,
+        width: config.cell_width,
+        size,
+        wrap,
+        bound: (config.tape.boundary == Boundary::Error).then_some(length),
+        off_tape: false,
+        commands: commands(source).collect(),
+        text: String::new(),
+        debug: debug.is_some(),
+        position: Position { line: 1, column: 1 },
+        run_pending: true,
+        run: None,
+        loops: Vec::new(),
+        multiplies: String::new(),
+        error: None,
+    };
+    lower(&program.body, config, &mut emitter);
+    emitter.end_run(emitter.commands.len());
+    if let Some(err) = emitter.error {
+        return Err(err);
+    }
+    let body = emitter.text;
+
+    let mut asm = String::new();
+    writeln!(asm, "# Generated by bf from {}.", module)?;
+    if let Some(source) = debug {
+        let (name, _) = dwarf::split(source);
+        writeln!(asm, "\t.file\t1 {}", quoted(&name))?;
+    }
+    writeln!(asm, "\t.text")?;
+    writeln!(asm, "\t.globl\t_start")?;
+    writeln!(asm, "_start:")?;
+    writeln!(asm, "\tleaq\ttape(%rip), %r12")?;
+    if wrap.is_some() {
+        writeln!(asm, "\tleaq\t{}(%r12), %r13", length * size)?;
+    }
+    if emitter.off_tape {
+        writeln!(asm, "\tmovq\t%r12, %rbx")?;
+    }
+    asm.push_str(&body);
+    writeln!(asm, "\t# exit(0)")?;
+    writeln!(asm, "\tmovl\t$60, %eax")?;
+    writeln!(asm, "\txorl\t%edi, %edi")?;
+    writeln!(asm, "\tsyscall")?;
+    if debug.is_some() {
+        writeln!(asm, ".Letext0:")?;
+        // Without `.loc` directives, code in another section has no line
+        // information, so stepping does not enter the subroutines.
+        writeln!(asm)?;
+        writeln!(asm, "\t.section\t.text.io,\"ax\",@progbits")?;
+    }
+    asm.push_str(SUBROUTINES);
+    if emitter.off_tape {
+        writeln!(asm)?;
+        writeln!(asm, "# Reports a move off the tape and exits.")?;
+        writeln!(asm, "off_tape:")?;
+        writeln!(asm, "\tmovl\t$1, %eax")?;
+        writeln!(asm, "\tmovl\t$2, %edi")?;
+        writeln!(asm, "\tleaq\toff_tape_message(%rip), %rsi")?;
+        writeln!(asm, "\tmovl\t${}, %edx", OFF_TAPE_MESSAGE.len())?;
+        writeln!(asm, "\tsyscall")?;
+        writeln!(asm, "\tmovl\t$60, %eax")?;
+        writeln!(asm, "\tmovl\t${}, %edi", OFF_TAPE_STATUS)?;
+        writeln!(asm, "\tsyscall")?;
+        writeln!(asm)?;
+        writeln!(asm, "\t.section\t.rodata")?;
+        writeln!(asm, "off_tape_message:")?;
+        writeln!(asm, "\t.ascii\t\"{}\\n\"", OFF_TAPE_MESSAGE.trim_end())?;
+    }
+    writeln!(asm)?;
+    writeln!(asm, "\t.bss")?;
+    writeln!(asm, "\t.p2align\t4")?;
+    writeln!(asm, "tape:")?;
+    writeln!(asm, "\t.zero\t{}", length * size)?;
+    writeln!(asm, "input_byte:")?;
+    writeln!(asm, "\t.zero\t1")?;
+    if let Some(source) = debug {
+        debug_info(&mut asm, source, config)?;
+    }
+    Ok(asm)
+}
+
+/// Quotes `value` as an assembler string.
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes the DWARF entries for `_start` and the tape, in the layout of
+/// [`dwarf::sections`].
+fn debug_info(asm: &mut String, source: &Path, config: &Config) -> std::fmt::Result {
+    let (name, directory) = dwarf::split(source);
+    writeln!(asm)?;
+    writeln!(asm, "\t.section\t.debug_abbrev,\"\",@progbits")?;
+    writeln!(asm, ".Ldebug_abbrev0:")?;
+    for bytes in dwarf::abbreviations().chunks(16) {
+        let bytes: Vec<_> = bytes.iter().map(|byte| byte.to_string()).collect();
+        writeln!(asm, "\t.byte\t{}", bytes.join(", "))?;
+    }
+    writeln!(asm, "\t.section\t.debug_info,\"\",@progbits")?;
+    writeln!(asm, ".Ldebug_info0:")?;
+    writeln!(asm, "\t.long\t.Ldebug_info_end - .Ldebug_info_start")?;
+    writeln!(asm, ".Ldebug_info_start:")?;
+    writeln!(asm, "\t.value\t4\t\t\t\t# version")?;
+    writeln!(asm, "\t.long\t.Ldebug_abbrev0")?;
+    writeln!(asm, "\t.byte\t8\t\t\t\t# address size")?;
+    writeln!(
+        asm,
+        "\t.byte\t{}\t\t\t\t# compile unit",
+        dwarf::COMPILE_UNIT
+    )?;
+    writeln!(asm, "\t.string\t{}", quoted(dwarf::PRODUCER))?;
+    writeln!(asm, "\t.value\t{}", dwarf::LANGUAGE)?;
+    writeln!(asm, "\t.string\t{}", quoted(&name))?;
+    writeln!(asm, "\t.string\t{}", quoted(&directory))?;
+    writeln!(asm, "\t.quad\t_start")?;
+    writeln!(asm, "\t.quad\t.Letext0 - _start")?;
+    writeln!(asm, "\t.long\t.Ldebug_line0")?;
+    writeln!(asm, ".Ldebug_cell:")?;
+    writeln!(asm, "\t.byte\t{}\t\t\t\t# base type", dwarf::BASE_TYPE)?;
+    writeln!(asm, "\t.string\t\"cell\"")?;
+    writeln!(asm, "\t.byte\t{}", dwarf::UNSIGNED)?;
+    writeln!(asm, "\t.byte\t{}", config.cell_width.bits() / 8)?;
+    writeln!(asm, ".Ldebug_array:")?;
+    writeln!(asm, "\t.byte\t{}\t\t\t\t# array type", dwarf::ARRAY_TYPE)?;
+    writeln!(asm, "\t.long\t.Ldebug_cell - .Ldebug_info0")?;
+    writeln!(asm, "\t.byte\t{}\t\t\t\t# subrange", dwarf::SUBRANGE_TYPE)?;
+    writeln!(asm, "\t.quad\t{}", config.tape.length - 1)?;
+    writeln!(asm, "\t.byte\t0")?;
+    writeln!(asm, "\t.byte\t{}\t\t\t\t# variable", dwarf::VARIABLE)?;
+    writeln!(asm, "\t.string\t\"tape\"")?;
+    writeln!(asm, "\t.long\t.Ldebug_array - .Ldebug_info0")?;
+    writeln!(asm, "\t.byte\t9")?;
+    writeln!(asm, "\t.byte\t{}", dwarf::OP_ADDRESS)?;
+    writeln!(asm, "\t.quad\ttape")?;
+    writeln!(asm, "\t.byte\t{}\t\t\t\t# subprogram", dwarf::SUBPROGRAM)?;
+    writeln!(asm, "\t.string\t\"_start\"")?;
+    writeln!(asm, "\t.quad\t_start")?;
+    writeln!(asm, "\t.quad\t.Letext0 - _start")?;
+    writeln!(asm, "\t.byte\t0")?;
+    writeln!(asm, ".Ldebug_info_end:")?;
+    writeln!(asm, "\t.section\t.debug_line,\"\",@progbits")?;
+    writeln!(asm, ".Ldebug_line0:")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interpreter::{
+        config::TapeConfig,
+        optimize::{optimize, Passes},
+    };
+
+    fn target() -> Target {
+        "x86_64-unknown-linux-gnu".parse().unwrap()
+    }
+
+    fn emit(source: &str, config: &Config) -> String {
+        let source: Vec<char> = source.chars().collect();
+        let mut program = Module::parse(&source).unwrap();
+        optimize(&mut program, &Passes::level(2), config);
+        emit_asm(&program, &source, "t.bf", config, Some(&target()), None).unwrap()
+    }
+
+    #[test]
+    fn labels_and_fragments() {
+        let asm = emit("++ comment\n[>+<-]>.\n,[\n  >]", &Config::default());
+        assert!(asm.starts_with("# Generated by bf from t.bf.\n"));
+        assert!(asm.contains(
+            "\t# ++[>+<-]>.,[>]
+\taddb\t$2, (%r12)
+\tcmpb\t$0, (%r12)
+\tje\t.Lmul_2_1
+\tmovzbl\t(%r12), %eax
+\tleaq\t1(%r12), %rcx
+\tmovq\t%rcx, %rdx
+\tsubq\t%rbx, %rdx
+\tcmpq\t$30000, %rdx
+\tjae\toff_tape
+\taddb\t%al, (%rcx)
+.Lmul_2_1:\t\t\t\t# multiply done
+"
+        ));
+        assert!(asm.contains("\tleaq\ttape(%rip), %r12\n\tmovq\t%r12, %rbx\n"));
+        assert!(asm.contains("off_tape:\n"));
+        assert!(asm.contains("\t.ascii\t\"pointer moved off the tape\\n\"\n"));
+        assert!(asm.contains(".Lscan_3_2:\t\t\t\t# scan\n"));
+        assert!(asm.contains("\tcall\tread_byte\n"));
+        assert!(asm.contains("tape:\n\t.zero\t30000\n"));
+
+        let asm = emit(",[.,]", &Config::default());
+        assert!(asm.contains(".Lloop_1_2:\t\t\t\t# [ at 1:2\n\t# .,\n"));
+        assert!(asm.contains("\tjne\t.Lloop_1_2\n.Lend_1_2:\t\t\t\t# ] at 1:5\n"));
+    }
+
+    #[test]
+    fn long_fragments() {
+        let asm = emit(&".>".repeat(40), &Config::default());
+        assert!(asm.contains(&format!("\t# {}...\n", ".>".repeat(30))));
+    }
+
+    #[test]
+    fn debug_directives() {
+        let source: Vec<char> = "+[\n-]".chars().collect();
+        let program = Module::parse(&source).unwrap();
+        let debug = Path::new("/src/t.bf");
+        let asm = emit_asm(
+            &program,
+            &source,
+            "t.bf",
+            &Config::default(),
+            Some(&target()),
+            Some(debug),
+        )
+        .unwrap();
+        assert!(asm.contains("\t.file\t1 \"t.bf\"\n"));
+        assert!(asm.contains("\t.loc\t1 1 1\n\taddb\t$1, (%r12)\n"));
+        assert!(asm.contains("\t.loc\t1 2 2\n\tcmpb\t$0, (%r12)\n\tjne\t.Lloop_1_2\n"));
+        assert!(asm.contains(".Letext0:\n\n\t.section\t.text.io,\"ax\",@progbits\n"));
+        assert!(asm.contains("\t.string\t\"/src\"\n"));
+        assert!(asm.ends_with("\t.section\t.debug_line,\"\",@progbits\n.Ldebug_line0:\n"));
+    }
+
+    /// Assembles and links `source` with `as` and `ld` into `executable`.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn build(source: &str, config: &Config, executable: &Path) {
+        use std::process::Command;
+
+        let asm = executable.with_extension("s");
+        let object = executable.with_extension("o");
+        std::fs::write(&asm, emit(source, config)).unwrap();
+        let status = Command::new("as")
+            .arg(&asm)
+            .arg("-o")
+            .arg(&object)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let status = Command::new("ld")
+            .arg(&object)
+            .arg("-o")
+            .arg(executable)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn assembles_samples() {
+        use crate::samples::{check_samples, installed, run_sample};
+
+        if !installed("as") || !installed("ld") {
+            return;
+        }
+        check_samples(build);
+        let wrapping = Config {
+            cell_width: CellWidth::Bits16,
+            eof: EofBehavior::MinusOne,
+            tape: TapeConfig {
+                length: 10,
+                boundary: Boundary::Wrap,
+            },
+            ..Config::default()
+        };
+        // 256 does not fit in 8 bits; cell 9 is reached by wrapping.
+        let output = run_sample(
+            "++++++++[<++++++++[<++++>-]>-]<<[>>+<<-]>>-.+.,.,.",
+            &wrapping,
+            b"a",
+            build,
+        );
+        assert_eq!(output.stdout, b"\xff\0a\xff");
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn stops_off_the_tape() {
+        use crate::samples::{check_stops_off_the_tape, installed};
+
+        if installed("as") && installed("ld") {
+            check_stops_off_the_tape(build);
+        }
+    }
+}
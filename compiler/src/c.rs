@@ -0,0 +1,341 @@
+use std::fmt::Write;
+
+use interpreter::{
+    config::{Boundary, CellWidth, Config, EofBehavior, TapeConfig},
+    ir::{Module, Position},
+};
+
+use crate::lower::{check, lower, uses_input, Lower, OFF_TAPE_MESSAGE, OFF_TAPE_STATUS};
+
+struct Emitter {
+    eof: EofBehavior,
+    tape: TapeConfig,
+    width: CellWidth,
+    body: String,
+    indent: usize,
+    /// Whether any check calls `off_tape`.
+    off_tape: bool,
+}
+
+impl Emitter {
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.body.push_str("    ");
+        }
+        self.body.push_str(line);
+        self.body.push('\n');
+    }
+
+    /// The tape index `offset` cells from `p`, wrapping around the tape with
+    /// [`Boundary::Wrap`].
+    fn index(&self, offset: isize) -> String {
+        if offset == 0 {
+            return "p".to_string();
+        }
+        if self.tape.boundary == Boundary::Wrap {
+            return format!("(p + {}) % TAPE_SIZE", offset);
+        }
+        if offset < 0 {
+            format!("p - {}", offset.unsigned_abs())
+        } else {
+            format!("p + {}", offset)
+        }
+    }
+
+    fn cell(&self, offset: isize) -> String {
+        format!("tape[{}]", self.index(offset))
+    }
+
+    /// The statement moving `p` by `offset`.
+    fn step(&self, offset: isize) -> String {
+        if self.tape.boundary == Boundary::Wrap {
+            return format!("p = {};", self.index(offset));
+        }
+        if offset < 0 {
+            format!("p -= {};", offset.unsigned_abs())
+        } else {
+            format!("p += {};", offset)
+        }
+    }
+
+    /// Calls `off_tape` unless the cell `offset` cells from `p` is on the
+    /// tape, with [`Boundary::Error`]. Indices left of the tape wrap around
+    /// to large unsigned numbers.
+    fn check_bounds(&mut self, offset: isize) {
+        if self.tape.boundary == Boundary::Error {
+            let line = format!("if ({} >= TAPE_SIZE) off_tape();", self.index(offset));
+            self.line(&line);
+            self.off_tape = true;
+        }
+    }
+
+    fn indented(&mut self, line: &str) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    fn close(&mut self) {
+        self.indent -= 1;
+        self.line("}");
+    }
+}
+
+impl Lower for Emitter {
+    fn add(&mut self, amount: i64) {
+        let line = format!("tape[p] {};", update(amount));
+        self.line(&line);
+    }
+
+    fn shift(&mut self, offset: isize) {
+        let line = self.step(offset);
+        self.line(&line);
+        self.check_bounds(0);
+    }
+
+    fn output(&mut self) {
+        if self.width == CellWidth::Bits8 {
+            self.line("putchar(tape[p]);");
+        } else {
+            self.line("putchar((unsigned char)tape[p]);");
+        }
+    }
+
+    fn input(&mut self) {
+        self.line("c = getchar();");
+        match self.eof {
+            EofBehavior::Unchanged => self.line("if (c != EOF) tape[p] = (cell)c;"),
+            EofBehavior::Zero => self.line("tape[p] = c == EOF ? 0 : (cell)c;"),
+            EofBehavior::MinusOne => self.line("tape[p] = c == EOF ? (cell)-1 : (cell)c;"),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.line("tape[p] = 0;");
+    }
+
+    fn loop_start(&mut self, label: usize, position: Position) {
+        let line = format!(
+            "while (tape[p]) {{ /* loop{} at {}:{} */",
+            label, position.line, position.column
+        );
+        self.indented(&line);
+    }
+
+    fn loop_end(&mut self, _: usize) {
+        self.close();
+    }
+
+    fn scan(&mut self, _: usize, _: Position, stride: isize) {
+        if self.tape.boundary == Boundary::Error {
+            self.indented("while (tape[p]) {");
+            self.shift(stride);
+            self.close();
+        } else {
+            let line = format!("while (tape[p]) {}", self.step(stride));
+            self.line(&line);
+        }
+    }
+
+    fn mul_start(&mut self, _: usize) {
+        self.indented("if (tape[p]) {");
+    }
+
+    fn mul(&mut self, offset: isize, factor: i64) {
+        let operator = if factor < 0 { "-=" } else { "+=" };
+        // Unsigned factors keep 16-bit products from overflowing the `int`
+        // they are promoted to.
+        let product = match factor.unsigned_abs() {
+            1 => "tape[p]".to_string(),
+            factor => format!("tape[p] * {}u", factor),
+        };
+        let line = format!("{} {} {};", self.cell(offset), operator, product);
+        self.check_bounds(offset);
+        self.line(&line);
+    }
+
+    fn mul_end(&mut self, _: usize) {
+        self.close();
+    }
+}
+
+/// `+=` or `-=` followed by the magnitude of `amount`.
+fn update(amount: i64) -> String {
+    let operator = if amount < 0 { "-=" } else { "+=" };
+    format!("{} {}", operator, literal(amount.unsigned_abs()))
+}
+
+/// A decimal constant, marked unsigned when it does not fit in a `long`.
+fn literal(value: u64) -> String {
+    if value > i64::MAX as u64 {
+        format!("{}u", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Translates a BF module into a C99 translation unit.
+///
+/// The tape is the static `tape` array of `TAPE_SIZE` cells of type `cell`,
+/// indexed by `p`. Each loop is a `while` commented with its number and
+/// source position, and optimized operations address cells relative to
+/// `p`, e.g. `tape[p + 2] += tape[p] * 3u;`. Multiplies are guarded by
+/// `if (tape[p])`, so they never touch cells the original loop would not.
+/// Unless the tape wraps, every move and multiply checks its cell, calling
+/// `off_tape` to report a move off the tape and exit with status 5.
+pub fn emit_c(program: &Module, module: &str, config: &Config) -> anyhow::Result<String> {
+    check(config)?;
+    let mut emitter = Emitter {
+        eof: config.eof,
+        tape: config.tape,
+        width: config.cell_width,
+        body: String::new(),
+        indent: 1,
+        off_tape: false,
+    };
+    if !program.body.is_empty() {
+        emitter.line("size_t p = 0;");
+    }
+    if uses_input(&program.body) {
+        emitter.line("int c;");
+    }
+    lower(&program.body, config, &mut emitter);
+    emitter.line("return 0;");
+
+    let mut c = String::new();
+    writeln!(c, "/* Generated by bf from {}. */", module)?;
+    writeln!(c, "#include <stdint.h>")?;
+    writeln!(c, "#include <stdio.h>")?;
+    if emitter.off_tape {
+        writeln!(c, "#include <stdlib.h>")?;
+    }
+    writeln!(c)?;
+    writeln!(c, "#define TAPE_SIZE {}", config.tape.length)?;
+    writeln!(c)?;
+    writeln!(c, "typedef uint{}_t cell;", config.cell_width.bits())?;
+    writeln!(c)?;
+    writeln!(c, "static cell tape[TAPE_SIZE];")?;
+    writeln!(c)?;
+    if emitter.off_tape {
+        writeln!(c, "static void off_tape(void) {{")?;
+        writeln!(
+            c,
+            "    fputs(\"{}\\n\", stderr);",
+            OFF_TAPE_MESSAGE.trim_end()
+        )?;
+        writeln!(c, "    exit({});", OFF_TAPE_STATUS)?;
+        writeln!(c, "}}")?;
+        writeln!(c)?;
+    }
+    writeln!(c, "int main(void) {{")?;
+    c.push_str(&emitter.body);
+    writeln!(c, "}}")?;
+    Ok(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command};
+
+    use super::*;
+    use crate::samples::{check_samples, check_stops_off_the_tape, installed};
+    use interpreter::optimize::{optimize, Passes};
+
+    fn emit(source: &str, config: &Config) -> String {
+        let mut program = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut program, &Passes::level(2), config);
+        emit_c(&program, "t.bf", config).unwrap()
+    }
+
+    #[test]
+    fn translation_unit() {
+        let c = emit("+[->+++>-<<]>.\n[<],", &Config::default());
+        assert!(c.starts_with("/* Generated by bf from t.bf. */\n"));
+        assert!(c.contains("#define TAPE_SIZE 30000\n"));
+        assert!(c.contains("typedef uint8_t cell;\n"));
+        assert!(c.contains(
+            "static void off_tape(void) {
+    fputs(\"pointer moved off the tape\\n\", stderr);
+    exit(5);
+}
+"
+        ));
+        assert!(c.contains(
+            "    tape[p] += 1;
+    if (tape[p]) {
+        if (p + 1 >= TAPE_SIZE) off_tape();
+        tape[p + 1] += tape[p] * 3u;
+        if (p + 2 >= TAPE_SIZE) off_tape();
+        tape[p + 2] -= tape[p];
+    }
+    tape[p] = 0;
+    p += 1;
+    if (p >= TAPE_SIZE) off_tape();
+    putchar(tape[p]);
+    while (tape[p]) {
+        p -= 1;
+        if (p >= TAPE_SIZE) off_tape();
+    }
+    c = getchar();
+    if (c != EOF) tape[p] = (cell)c;
+    return 0;
+}
+"
+        ));
+    }
+
+    #[test]
+    fn named_loops() {
+        let c = emit(",[>,[.-]<-]", &Config::default());
+        assert!(c.contains("    while (tape[p]) { /* loop1 at 1:2 */\n"));
+        assert!(c.contains("        while (tape[p]) { /* loop2 at 1:5 */\n"));
+    }
+
+    #[test]
+    fn cell_type_and_tape() {
+        let config = Config {
+            cell_width: CellWidth::Bits64,
+            eof: EofBehavior::MinusOne,
+            tape: TapeConfig {
+                length: 100,
+                boundary: Boundary::Wrap,
+            },
+            ..Config::default()
+        };
+        let c = emit("<-.,", &config);
+        assert!(c.contains("#define TAPE_SIZE 100\n"));
+        assert!(c.contains("typedef uint64_t cell;\n"));
+        assert!(c.contains("p = (p + 99) % TAPE_SIZE;\n"));
+        assert!(!c.contains("off_tape"));
+        assert!(c.contains("tape[p] -= 1;\n"));
+        assert!(c.contains("putchar((unsigned char)tape[p]);\n"));
+        assert!(c.contains("tape[p] = c == EOF ? (cell)-1 : (cell)c;\n"));
+        assert_eq!(literal(1 << 63), "9223372036854775808u");
+    }
+
+    /// Compiles `source` with `cc` into `executable`.
+    fn build(source: &str, config: &Config, executable: &Path) {
+        let c = executable.with_extension("c");
+        std::fs::write(&c, emit(source, config)).unwrap();
+        let status = Command::new("cc")
+            .args(["-std=c99", "-Wall", "-Werror", "-o"])
+            .arg(executable)
+            .arg(&c)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn compiles_samples() {
+        if installed("cc") {
+            check_samples(build);
+        }
+    }
+
+    #[test]
+    fn stops_off_the_tape() {
+        if installed("cc") {
+            check_stops_off_the_tape(build);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! DWARF 4 debug information for the native backends.
+//!
+//! Both describe one compile unit with a `_start` subprogram and the tape as
+//! a `tape` array of `cell`s. The unit claims to be C99, so debuggers print
+//! the tape like a C array. The ELF backend encodes every section with
+//! [`sections`]; the assembler backend writes the same entries as directives
+//! and leaves the line table to `as`.
+
+use std::path::Path;
+
+use interpreter::ir::Position;
+
+use crate::leb128::{signed, unsigned};
+
+pub(crate) const COMPILE_UNIT: u8 = 1;
+pub(crate) const SUBPROGRAM: u8 = 2;
+pub(crate) const BASE_TYPE: u8 = 3;
+pub(crate) const ARRAY_TYPE: u8 = 4;
+pub(crate) const SUBRANGE_TYPE: u8 = 5;
+pub(crate) const VARIABLE: u8 = 6;
+
+/// `DW_LANG_C99`.
+pub(crate) const LANGUAGE: u16 = 0x0c;
+/// `DW_ATE_unsigned`.
+pub(crate) const UNSIGNED: u8 = 0x07;
+/// `DW_OP_addr`, followed by an 8-byte address.
+pub(crate) const OP_ADDRESS: u8 = 0x03;
+pub(crate) const PRODUCER: &str = "bf";
+
+// Tags
+const TAG_ARRAY_TYPE: u8 = 0x01;
+const TAG_COMPILE_UNIT: u8 = 0x11;
+const TAG_SUBRANGE_TYPE: u8 = 0x21;
+const TAG_BASE_TYPE: u8 = 0x24;
+const TAG_SUBPROGRAM: u8 = 0x2e;
+const TAG_VARIABLE: u8 = 0x34;
+
+// Attributes
+const AT_LOCATION: u8 = 0x02;
+const AT_NAME: u8 = 0x03;
+const AT_BYTE_SIZE: u8 = 0x0b;
+const AT_STMT_LIST: u8 = 0x10;
+const AT_LOW_PC: u8 = 0x11;
+const AT_HIGH_PC: u8 = 0x12;
+const AT_LANGUAGE: u8 = 0x13;
+const AT_COMP_DIR: u8 = 0x1b;
+const AT_PRODUCER: u8 = 0x25;
+const AT_UPPER_BOUND: u8 = 0x2f;
+const AT_ENCODING: u8 = 0x3e;
+const AT_EXTERNAL: u8 = 0x3f;
+const AT_TYPE: u8 = 0x49;
+
+// Forms
+const FORM_ADDR: u8 = 0x01;
+const FORM_DATA2: u8 = 0x05;
+const FORM_DATA8: u8 = 0x07;
+const FORM_STRING: u8 = 0x08;
+const FORM_DATA1: u8 = 0x0b;
+const FORM_REF4: u8 = 0x13;
+const FORM_SEC_OFFSET: u8 = 0x17;
+const FORM_EXPRLOC: u8 = 0x18;
+const FORM_FLAG_PRESENT: u8 = 0x19;
+
+/// The tag and attribute forms of one kind of entry.
+struct Abbreviation {
+    code: u8,
+    tag: u8,
+    children: bool,
+    /// Pairs of attribute and form.
+    attributes: &'static [(u8, u8)],
+}
+
+const ABBREVIATIONS: [Abbreviation; 6] = [
+    Abbreviation {
+        code: COMPILE_UNIT,
+        tag: TAG_COMPILE_UNIT,
+        children: true,
+        attributes: &[
+            (AT_PRODUCER, FORM_STRING),
+            (AT_LANGUAGE, FORM_DATA2),
+            (AT_NAME, FORM_STRING),
+            (AT_COMP_DIR, FORM_STRING),
+            (AT_LOW_PC, FORM_ADDR),
+            (AT_HIGH_PC, FORM_DATA8),
+            (AT_STMT_LIST, FORM_SEC_OFFSET),
+        ],
+    },
+    Abbreviation {
+        code: SUBPROGRAM,
+        tag: TAG_SUBPROGRAM,
+        children: false,
+        attributes: &[
+            (AT_NAME, FORM_STRING),
+            (AT_EXTERNAL, FORM_FLAG_PRESENT),
+            (AT_LOW_PC, FORM_ADDR),
+            (AT_HIGH_PC, FORM_DATA8),
+        ],
+    },
+    Abbreviation {
+        code: BASE_TYPE,
+        tag: TAG_BASE_TYPE,
+        children: false,
+        attributes: &[
+            (AT_NAME, FORM_STRING),
+            (AT_ENCODING, FORM_DATA1),
+            (AT_BYTE_SIZE, FORM_DATA1),
+        ],
+    },
+    Abbreviation {
+        code: ARRAY_TYPE,
+        tag: TAG_ARRAY_TYPE,
+        children: true,
+        attributes: &[(AT_TYPE, FORM_REF4)],
+    },
+    Abbreviation {
+        code: SUBRANGE_TYPE,
+        tag: TAG_SUBRANGE_TYPE,
+        children: false,
+        attributes: &[(AT_UPPER_BOUND, FORM_DATA8)],
+    },
+    Abbreviation {
+        code: VARIABLE,
+        tag: TAG_VARIABLE,
+        children: false,
+        attributes: &[
+            (AT_NAME, FORM_STRING),
+            (AT_TYPE, FORM_REF4),
+            (AT_EXTERNAL, FORM_FLAG_PRESENT),
+            (AT_LOCATION, FORM_EXPRLOC),
+        ],
+    },
+];
+
+/// The contents of `.debug_abbrev`.
+pub(crate) fn abbreviations() -> Vec<u8> {
+    let mut out = Vec::new();
+    for abbreviation in &ABBREVIATIONS {
+        unsigned(&mut out, abbreviation.code as u64);
+        unsigned(&mut out, abbreviation.tag as u64);
+        out.push(abbreviation.children as u8);
+        for (attribute, form) in abbreviation.attributes {
+            unsigned(&mut out, *attribute as u64);
+            unsigned(&mut out, *form as u64);
+        }
+        out.extend_from_slice(&[0, 0]);
+    }
+    out.push(0);
+    out
+}
+
+/// The name and directory of `source`, as recorded in the compile unit.
+pub(crate) fn split(source: &Path) -> (String, String) {
+    let name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let directory = source
+        .parent()
+        .map(|directory| directory.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (name, directory)
+}
+
+/// A program loaded at fixed addresses.
+pub(crate) struct Program<'a> {
+    pub(crate) source: &'a Path,
+    /// The address of the first instruction.
+    pub(crate) code: u64,
+    pub(crate) code_size: u64,
+    /// The address of the tape.
+    pub(crate) tape: u64,
+    pub(crate) tape_length: u64,
+    pub(crate) cell_size: u8,
+    /// The address each line table row starts at, in ascending order, and
+    /// the source it was compiled from.
+    pub(crate) rows: &'a [(u64, Position)],
+}
+
+/// Encodes `.debug_abbrev`, `.debug_info` and `.debug_line`, by name.
+pub(crate) fn sections(program: &Program) -> [(&'static str, Vec<u8>); 3] {
+    [
+        (".debug_abbrev", abbreviations()),
+        (".debug_info", info(program)),
+        (".debug_line", line_program(program)),
+    ]
+}
+
+fn string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(value.as_bytes());
+    out.push(0);
+}
+
+fn info(program: &Program) -> Vec<u8> {
+    let (name, directory) = split(program.source);
+    let mut out = Vec::new();
+    out.extend_from_slice(&0u32.to_le_bytes()); // unit length, patched below
+    out.extend_from_slice(&4u16.to_le_bytes()); // version
+    out.extend_from_slice(&0u32.to_le_bytes()); // abbreviations offset
+    out.push(8); // address size
+
+    out.push(COMPILE_UNIT);
+    string(&mut out, PRODUCER);
+    out.extend_from_slice(&LANGUAGE.to_le_bytes());
+    string(&mut out, &name);
+    string(&mut out, &directory);
+    out.extend_from_slice(&program.code.to_le_bytes());
+    out.extend_from_slice(&program.code_size.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // line table offset
+
+    // References are offsets from the start of the unit.
+    let cell = out.len() as u32;
+    out.push(BASE_TYPE);
+    string(&mut out, "cell");
+    out.push(UNSIGNED);
+    out.push(program.cell_size);
+
+    let array = out.len() as u32;
+    out.push(ARRAY_TYPE);
+    out.extend_from_slice(&cell.to_le_bytes());
+    out.push(SUBRANGE_TYPE);
+    out.extend_from_slice(&(program.tape_length - 1).to_le_bytes());
+    out.push(0);
+
+    out.push(VARIABLE);
+    string(&mut out, "tape");
+    out.extend_from_slice(&array.to_le_bytes());
+    out.push(9);
+    out.push(OP_ADDRESS);
+    out.extend_from_slice(&program.tape.to_le_bytes());
+
+    out.push(SUBPROGRAM);
+    string(&mut out, "_start");
+    out.extend_from_slice(&program.code.to_le_bytes());
+    out.extend_from_slice(&program.code_size.to_le_bytes());
+    out.push(0);
+
+    let length = out.len() as u32 - 4;
+    out[..4].copy_from_slice(&length.to_le_bytes());
+    out
+}
+
+// Standard line program opcodes
+const LNS_COPY: u8 = 0x01;
+const LNS_ADVANCE_PC: u8 = 0x02;
+const LNS_ADVANCE_LINE: u8 = 0x03;
+const LNS_SET_COLUMN: u8 = 0x05;
+// Extended line program opcodes
+const LNE_END_SEQUENCE: u8 = 0x01;
+const LNE_SET_ADDRESS: u8 = 0x02;
+
+fn line_program(program: &Program) -> Vec<u8> {
+    let (name, _) = split(program.source);
+    let mut out = Vec::new();
+    out.extend_from_slice(&0u32.to_le_bytes()); // unit length, patched below
+    out.extend_from_slice(&4u16.to_le_bytes()); // version
+    out.extend_from_slice(&0u32.to_le_bytes()); // header length, patched below
+    let header = out.len();
+    out.push(1); // minimum instruction length
+    out.push(1); // maximum operations per instruction
+    out.push(1); // default is_stmt
+    out.push(-5i8 as u8); // line base
+    out.push(14); // line range
+    out.push(13); // opcode base
+    out.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+    out.push(0); // no include directories
+    string(&mut out, &name);
+    out.extend_from_slice(&[0, 0, 0]); // the compile directory, no time or size
+    out.push(0);
+    let header_length = (out.len() - header) as u32;
+    out[6..10].copy_from_slice(&header_length.to_le_bytes());
+
+    out.extend_from_slice(&[0, 9, LNE_SET_ADDRESS]);
+    out.extend_from_slice(&program.code.to_le_bytes());
+    let (mut address, mut line, mut column) = (program.code, 1, 0);
+    for &(row, position) in program.rows {
+        if position.line != line {
+            out.push(LNS_ADVANCE_LINE);
+            signed(&mut out, position.line as i64 - line as i64);
+            line = position.line;
+        }
+        if position.column != column {
+            out.push(LNS_SET_COLUMN);
+            unsigned(&mut out, position.column as u64);
+            column = position.column;
+        }
+        if row != address {
+            out.push(LNS_ADVANCE_PC);
+            unsigned(&mut out, row - address);
+            address = row;
+        }
+        out.push(LNS_COPY);
+    }
+    out.push(LNS_ADVANCE_PC);
+    unsigned(&mut out, program.code + program.code_size - address);
+    out.extend_from_slice(&[0, 1, LNE_END_SEQUENCE]);
+
+    let length = out.len() as u32 - 4;
+    out[..4].copy_from_slice(&length.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_program_rows() {
+        let rows = [
+            (0x1000, Position { line: 1, column: 1 }),
+            (0x1005, Position { line: 1, column: 3 }),
+            (0x1010, Position { line: 4, column: 2 }),
+        ];
+        let program = Program {
+            source: Path::new("/src/t.bf"),
+            code: 0x1000,
+            code_size: 0x20,
+            tape: 0x2000,
+            tape_length: 100,
+            cell_size: 1,
+            rows: &rows,
+        };
+        let [_, (_, info), (_, line)] = sections(&program);
+        let mut rows = vec![0, 9, LNE_SET_ADDRESS];
+        rows.extend_from_slice(&0x1000u64.to_le_bytes());
+        rows.extend_from_slice(&[LNS_SET_COLUMN, 1, LNS_COPY]);
+        rows.extend_from_slice(&[LNS_SET_COLUMN, 3, LNS_ADVANCE_PC, 5, LNS_COPY]);
+        rows.extend_from_slice(&[LNS_ADVANCE_LINE, 3, LNS_SET_COLUMN, 2]);
+        rows.extend_from_slice(&[LNS_ADVANCE_PC, 11, LNS_COPY]);
+        rows.extend_from_slice(&[LNS_ADVANCE_PC, 16, 0, 1, LNE_END_SEQUENCE]);
+        assert!(line.ends_with(&rows));
+        assert!(line.windows(5).any(|window| window == b"t.bf\0"));
+        assert_eq!(
+            u32::from_le_bytes(info[..4].try_into().unwrap()) as usize,
+            info.len() - 4
+        );
+        assert!(info.windows(5).any(|window| window == b"/src\0"));
+    }
+}
@@ -0,0 +1,585 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use interpreter::{
+    config::{Boundary, CellWidth, Config, EofBehavior},
+    ir::{Module, Node, Op, Position},
+};
+
+use crate::{
+    dwarf,
+    lower::{check, OFF_TAPE_MESSAGE, OFF_TAPE_STATUS},
+    target::Target,
+};
+
+/// Where the first byte of the file is loaded.
+const BASE: u64 = 0x40_0000;
+const PAGE: u64 = 0x1000;
+const HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+const SECTION_HEADER_SIZE: u64 = 64;
+/// The code follows the ELF header and both program headers.
+const CODE_OFFSET: u64 = HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+
+/// Emits x86-64 code that keeps the tape start in `rbx`, the current cell in
+/// `r12` and the tape end in `r13`.
+struct Assembler {
+    code: Vec<u8>,
+    eof: EofBehavior,
+    /// The tape length, if moves wrap around it.
+    wrap: Option<i32>,
+    /// The tape length, if moves off the tape are errors.
+    bound: Option<i32>,
+    /// The jumps taken on moves off the tape, to patch once the code
+    /// reporting them is placed.
+    off_tape: Vec<usize>,
+    /// The code offset and source of each operation, with `-g`.
+    rows: Option<Vec<(usize, Position)>>,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// Emits a jump with a 32-bit displacement to patch later, returning the
+    /// offset just past it.
+    fn jump(&mut self, opcode: &[u8]) -> usize {
+        self.emit(opcode);
+        self.emit_u32(0);
+        self.code.len()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let displacement = (target as i64 - at as i64) as i32;
+        self.code[at - 4..at].copy_from_slice(&displacement.to_le_bytes());
+    }
+
+    fn compare_zero(&mut self) {
+        self.emit(&[0x41, 0x80, 0x3c, 0x24, 0x00]); // cmp byte [r12], 0
+    }
+
+    /// An offset to add to a cell address. With a wrapping tape, the result
+    /// is at most one tape length past the end.
+    fn displacement(&self, offset: isize) -> anyhow::Result<i32> {
+        let offset = match self.wrap {
+            Some(length) => offset.rem_euclid(length as isize),
+            None => offset,
+        };
+        i32::try_from(offset).context("the ELF backend supports moves of up to 2^31 cells")
+    }
+
+    /// Jumps to the code reporting a move off the tape unless the index of
+    /// `rcx` is below `length`. Addresses left of the tape become large
+    /// unsigned indices.
+    fn check_bounds(&mut self, length: i32) {
+        self.emit(&[0x48, 0x29, 0xd9]); // sub rcx, rbx
+        self.emit(&[0x48, 0x81, 0xf9]); // cmp rcx, length
+        self.emit_u32(length as u32);
+        let jump = self.jump(&[0x0f, 0x83]); // jae
+        self.off_tape.push(jump);
+    }
+
+    fn shift(&mut self, offset: isize) -> anyhow::Result<()> {
+        let displacement = self.displacement(offset)?;
+        self.emit(&[0x49, 0x81, 0xc4]); // add r12, displacement
+        self.emit_u32(displacement as u32);
+        if let Some(length) = self.wrap {
+            self.emit(&[0x4d, 0x39, 0xec]); // cmp r12, r13
+            self.emit(&[0x72, 0x07]); // jb past the sub
+            self.emit(&[0x49, 0x81, 0xec]); // sub r12, length
+            self.emit_u32(length as u32);
+        }
+        if let Some(length) = self.bound {
+            self.emit(&[0x4c, 0x89, 0xe1]); // mov rcx, r12
+            self.check_bounds(length);
+        }
+        Ok(())
+    }
+
+    fn mul(&mut self, offset: isize, factor: i64) -> anyhow::Result<()> {
+        let displacement = self.displacement(offset)?;
+        self.compare_zero();
+        let skip = self.jump(&[0x0f, 0x84]); // je
+        self.emit(&[0x49, 0x8d, 0x84, 0x24]); // lea rax, [r12 + displacement]
+        self.emit_u32(displacement as u32);
+        if let Some(length) = self.wrap {
+            self.emit(&[0x4c, 0x39, 0xe8]); // cmp rax, r13
+            self.emit(&[0x72, 0x06]); // jb past the sub
+            self.emit(&[0x48, 0x2d]); // sub rax, length
+            self.emit_u32(length as u32);
+        }
+        if let Some(length) = self.bound {
+            self.emit(&[0x48, 0x89, 0xc1]); // mov rcx, rax
+            self.check_bounds(length);
+        }
+        self.emit(&[0x41, 0x0f, 0xb6, 0x0c, 0x24]); // movzx ecx, byte [r12]
+        self.emit(&[0x69, 0xc9]); // imul ecx, ecx, factor
+        self.emit_u32(factor as u8 as u32);
+        self.emit(&[0x00, 0x08]); // add byte [rax], cl
+        let end = self.code.len();
+        self.patch(skip, end);
+        Ok(())
+    }
+
+    /// Calls `read` or `write` on the current cell.
+    fn syscall(&mut self, number: u8, fd: u8) {
+        self.emit(&[0xb8, number, 0, 0, 0]); // mov eax, number
+        self.emit(&[0xbf, fd, 0, 0, 0]); // mov edi, fd
+        self.emit(&[0x4c, 0x89, 0xe6]); // mov rsi, r12
+        self.emit(&[0xba, 1, 0, 0, 0]); // mov edx, 1
+        self.emit(&[0x0f, 0x05]); // syscall
+    }
+
+    fn input(&mut self) {
+        self.syscall(0, 0);
+        let value = match self.eof {
+            EofBehavior::Unchanged => return,
+            EofBehavior::Zero => 0,
+            EofBehavior::MinusOne => 0xff,
+        };
+        // `read` returns 0 at end of input and a negative errno on failure.
+        self.emit(&[0x48, 0x85, 0xc0]); // test rax, rax
+        self.emit(&[0x7f, 0x05]); // jg past the mov
+        self.emit(&[0x41, 0xc6, 0x04, 0x24, value]); // mov byte [r12], value
+    }
+
+    /// Places the code the bounds checks jump to, which writes
+    /// [`OFF_TAPE_MESSAGE`] to stderr and exits with [`OFF_TAPE_STATUS`].
+    fn off_tape(&mut self) {
+        if self.off_tape.is_empty() {
+            return;
+        }
+        let start = self.code.len();
+        for jump in std::mem::take(&mut self.off_tape) {
+            self.patch(jump, start);
+        }
+        self.emit(&[0xb8, 1, 0, 0, 0]); // mov eax, 1 (write)
+        self.emit(&[0xbf, 2, 0, 0, 0]); // mov edi, 2
+
+        // The message follows the code.
+        let message = self.jump(&[0x48, 0x8d, 0x35]); // lea rsi, [rip + message]
+        self.emit(&[0xba]); // mov edx, length
+        self.emit_u32(OFF_TAPE_MESSAGE.len() as u32);
+        self.emit(&[0x0f, 0x05]); // syscall
+        self.emit(&[0xb8, 60, 0, 0, 0]); // mov eax, 60 (exit)
+        self.emit(&[0xbf]); // mov edi, status
+        self.emit_u32(OFF_TAPE_STATUS as u32);
+        self.emit(&[0x0f, 0x05]); // syscall
+        let end = self.code.len();
+        self.patch(message, end);
+        self.emit(OFF_TAPE_MESSAGE.as_bytes());
+    }
+
+    fn body(&mut self, body: &[Node]) -> anyhow::Result<()> {
+        for node in body {
+            if let Some(rows) = &mut self.rows {
+                rows.push((self.code.len(), node.position));
+            }
+            match &node.op {
+                // add byte [r12], amount
+                Op::Add(amount) => self.emit(&[0x41, 0x80, 0x04, 0x24, *amount as u8]),
+                Op::Move(offset) => self.shift(*offset)?,
+                Op::Output => self.syscall(1, 1),
+                Op::Input => self.input(),
+                Op::Loop(inner) => {
+                    self.compare_zero();
+                    let exit = self.jump(&[0x0f, 0x84]); // je
+                    let start = self.code.len();
+                    self.body(inner)?;
+                    self.compare_zero();
+                    let back = self.jump(&[0x0f, 0x85]); // jne
+                    self.patch(back, start);
+                    let end = self.code.len();
+                    self.patch(exit, end);
+                }
+                // mov byte [r12], 0
+                Op::Clear => self.emit(&[0x41, 0xc6, 0x04, 0x24, 0x00]),
+                Op::Scan(stride) => {
+                    let head = self.code.len();
+                    self.compare_zero();
+                    let exit = self.jump(&[0x0f, 0x84]); // je
+                    self.shift(*stride)?;
+                    let back = self.jump(&[0xe9]); // jmp
+                    self.patch(back, head);
+                    let end = self.code.len();
+                    self.patch(exit, end);
+                }
+                Op::Mul { offset, factor } => self.mul(*offset, *factor)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Assembles a BF module into a static x86-64 Linux executable.
+///
+/// The program talks to the kernel through the `read`, `write` and `exit`
+/// syscalls, so it needs no libc, and keeps the tape in a zero-initialized
+/// `.bss` section. Like [`emit_llvm`](crate::emit_llvm), it checks every
+/// move unless the tape wraps, and one off the tape writes a message to
+/// stderr and exits with status 5.
+///
+/// `target`, if known, must be x86-64 Linux.
+///
+/// With `debug`, the path of the source file, the executable also carries
+/// DWARF line information for each operation and describes the tape as a
+/// global array named `tape`.
+pub fn emit_elf(
+    program: &Module,
+    config: &Config,
+    target: Option<&Target>,
+    debug: Option<&Path>,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(target) = target {
+        target.require_x86_64_linux("the ELF backend")?;
+    }
+    check(config)?;
+    if config.cell_width != CellWidth::Bits8 {
+        bail!("the ELF backend supports 8-bit cells only; use --backend llvm");
+    }
+    let length = i32::try_from(config.tape.length)
+        .context("the ELF backend supports tapes of up to 2^31 cells")?;
+    let wrap = (config.tape.boundary == Boundary::Wrap).then_some(length);
+
+    let mut assembler = Assembler {
+        code: Vec::new(),
+        eof: config.eof,
+        wrap,
+        bound: (config.tape.boundary == Boundary::Error).then_some(length),
+        off_tape: Vec::new(),
+        rows: debug.map(|_| Vec::new()),
+    };
+    // The tape address is patched in below, once the code size is known.
+    assembler.emit(&[0x48, 0xbb]); // mov rbx, tape
+    assembler.emit(&[0; 8]);
+    assembler.emit(&[0x49, 0x89, 0xdc]); // mov r12, rbx
+    assembler.emit(&[0x4c, 0x8d, 0xab]); // lea r13, [rbx + length]
+    assembler.emit_u32(length as u32);
+    assembler.body(&program.body)?;
+    assembler.emit(&[0xb8, 60, 0, 0, 0]); // mov eax, 60 (exit)
+    assembler.emit(&[0x31, 0xff]); // xor edi, edi
+    assembler.emit(&[0x0f, 0x05]); // syscall
+    assembler.off_tape();
+    let mut code = assembler.code;
+
+    let code_end = CODE_OFFSET + code.len() as u64;
+    let tape = (BASE + code_end).next_multiple_of(PAGE);
+    code[2..10].copy_from_slice(&tape.to_le_bytes());
+    let mut debug_sections = Vec::new();
+    if let (Some(source), Some(rows)) = (debug, assembler.rows) {
+        let address = |offset: usize| BASE + CODE_OFFSET + offset as u64;
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|(offset, position)| (address(offset), position))
+            .collect();
+        debug_sections.extend(dwarf::sections(&dwarf::Program {
+            source,
+            code: address(0),
+            code_size: code.len() as u64,
+            tape,
+            tape_length: length as u64,
+            cell_size: 1,
+            rows: &rows,
+        }));
+    }
+    Ok(Image {
+        code,
+        tape,
+        tape_size: length as u64,
+        debug: debug_sections,
+    }
+    .write())
+}
+
+/// The parts of an executable, laid out as the ELF header, the program
+/// headers, the code, any debug sections, the section names and the section
+/// headers.
+struct Image {
+    code: Vec<u8>,
+    /// The address of the tape.
+    tape: u64,
+    tape_size: u64,
+    /// Unloaded sections, by name.
+    debug: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl Image {
+    fn write(&self) -> Vec<u8> {
+        let code_size = self.code.len() as u64;
+        let code_end = CODE_OFFSET + code_size;
+        let mut names = b"\0.text\0.bss\0".to_vec();
+        let mut debug = Vec::new();
+        let mut offset = code_end;
+        for (name, content) in &self.debug {
+            debug.push((names.len() as u32, offset, content));
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            offset += content.len() as u64;
+        }
+        let names_name = names.len() as u32;
+        names.extend_from_slice(b".shstrtab\0");
+        let names_offset = offset;
+        let sections_offset = (names_offset + names.len() as u64).next_multiple_of(8);
+        let sections = 4 + debug.len() as u16;
+        let mut file = Vec::new();
+
+        // ELF header
+        file.extend_from_slice(b"\x7fELF");
+        file.extend_from_slice(&[2, 1, 1, 0]); // 64-bit, little endian, version 1, System V
+        file.extend_from_slice(&[0; 8]);
+        put16(&mut file, 2); // executable
+        put16(&mut file, 0x3e); // x86-64
+        put32(&mut file, 1);
+        put64(&mut file, BASE + CODE_OFFSET); // entry point
+        put64(&mut file, HEADER_SIZE); // program headers
+        put64(&mut file, sections_offset);
+        put32(&mut file, 0);
+        put16(&mut file, HEADER_SIZE as u16);
+        put16(&mut file, PROGRAM_HEADER_SIZE as u16);
+        put16(&mut file, 2);
+        put16(&mut file, SECTION_HEADER_SIZE as u16);
+        put16(&mut file, sections);
+        put16(&mut file, sections - 1); // index of .shstrtab
+
+        // The headers and code, readable and executable.
+        program_header(&mut file, 5, 0, BASE, code_end, code_end);
+        // The tape, readable and writable, with nothing in the file.
+        program_header(&mut file, 6, 0, self.tape, 0, self.tape_size);
+
+        file.extend_from_slice(&self.code);
+        for (_, content) in &self.debug {
+            file.extend_from_slice(content);
+        }
+        file.extend_from_slice(&names);
+        file.resize(sections_offset as usize, 0);
+
+        file.extend_from_slice(&[0; SECTION_HEADER_SIZE as usize]);
+        section_header(
+            &mut file,
+            Section {
+                name: 1,  // .text
+                kind: 1,  // program data
+                flags: 6, // allocated, executable
+                address: BASE + CODE_OFFSET,
+                offset: CODE_OFFSET,
+                size: code_size,
+                align: 16,
+            },
+        );
+        section_header(
+            &mut file,
+            Section {
+                name: 7,  // .bss
+                kind: 8,  // no bits
+                flags: 3, // writable, allocated
+                address: self.tape,
+                offset: code_end,
+                size: self.tape_size,
+                align: 16,
+            },
+        );
+        for (name, offset, content) in debug {
+            section_header(
+                &mut file,
+                Section {
+                    name,
+                    kind: 1, // program data
+                    flags: 0,
+                    address: 0,
+                    offset,
+                    size: content.len() as u64,
+                    align: 1,
+                },
+            );
+        }
+        section_header(
+            &mut file,
+            Section {
+                name: names_name,
+                kind: 3, // string table
+                flags: 0,
+                address: 0,
+                offset: names_offset,
+                size: names.len() as u64,
+                align: 1,
+            },
+        );
+        file
+    }
+}
+
+fn program_header(
+    file: &mut Vec<u8>,
+    flags: u32,
+    offset: u64,
+    address: u64,
+    size: u64,
+    memory: u64,
+) {
+    put32(file, 1); // loadable
+    put32(file, flags);
+    put64(file, offset);
+    put64(file, address);
+    put64(file, address);
+    put64(file, size);
+    put64(file, memory);
+    put64(file, PAGE);
+}
+
+struct Section {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    address: u64,
+    offset: u64,
+    size: u64,
+    align: u64,
+}
+
+fn section_header(file: &mut Vec<u8>, section: Section) {
+    put32(file, section.name);
+    put32(file, section.kind);
+    put64(file, section.flags);
+    put64(file, section.address);
+    put64(file, section.offset);
+    put64(file, section.size);
+    put32(file, 0); // link
+    put32(file, 0); // info
+    put64(file, section.align);
+    put64(file, 0); // entry size
+}
+
+fn put16(file: &mut Vec<u8>, value: u16) {
+    file.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put32(file: &mut Vec<u8>, value: u32) {
+    file.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put64(file: &mut Vec<u8>, value: u64) {
+    file.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interpreter::config::TapeConfig;
+
+    fn target() -> Target {
+        "x86_64-unknown-linux-gnu".parse().unwrap()
+    }
+
+    #[test]
+    fn file_layout() {
+        let program = Module::parse(&"+.".chars().collect::<Vec<_>>()).unwrap();
+        let file = emit_elf(&program, &Config::default(), Some(&target()), None).unwrap();
+        assert_eq!(&file[..4], b"\x7fELF");
+        let entry = u64::from_le_bytes(file[24..32].try_into().unwrap());
+        assert_eq!(entry, BASE + CODE_OFFSET);
+        let tape = u64::from_le_bytes(file[CODE_OFFSET as usize + 2..][..8].try_into().unwrap());
+        assert_eq!(tape % PAGE, 0);
+        // The second program header maps 30000 zeroed cells at the tape.
+        let header = &file[(HEADER_SIZE + PROGRAM_HEADER_SIZE) as usize..];
+        assert_eq!(u64::from_le_bytes(header[16..24].try_into().unwrap()), tape);
+        assert_eq!(u64::from_le_bytes(header[32..40].try_into().unwrap()), 0);
+        assert_eq!(
+            u64::from_le_bytes(header[40..48].try_into().unwrap()),
+            30000
+        );
+    }
+
+    #[test]
+    fn debug_sections() {
+        let program = Module::parse(&"+.".chars().collect::<Vec<_>>()).unwrap();
+        let plain = emit_elf(&program, &Config::default(), Some(&target()), None).unwrap();
+        assert_eq!(u16::from_le_bytes(plain[60..62].try_into().unwrap()), 4);
+        let source = Path::new("/src/t.bf");
+        let file = emit_elf(&program, &Config::default(), Some(&target()), Some(source)).unwrap();
+        // .text, .bss, three debug sections and .shstrtab after the null one.
+        assert_eq!(u16::from_le_bytes(file[60..62].try_into().unwrap()), 7);
+        assert_eq!(u16::from_le_bytes(file[62..64].try_into().unwrap()), 6);
+        // The code is the same with or without debug information.
+        let sections = u64::from_le_bytes(plain[40..48].try_into().unwrap()) as usize;
+        let text = &plain[sections + SECTION_HEADER_SIZE as usize..];
+        let size = u64::from_le_bytes(text[32..40].try_into().unwrap()) as usize;
+        let code = CODE_OFFSET as usize..CODE_OFFSET as usize + size;
+        assert_eq!(file[code.clone()], plain[code]);
+        for name in [
+            &b".debug_info\0"[..],
+            b".debug_line\0",
+            b"t.bf\0",
+            b"/src\0",
+        ] {
+            assert!(file.windows(name.len()).any(|window| window == name));
+        }
+    }
+
+    #[test]
+    fn unsupported_settings() {
+        let program = Module::parse(&['+']).unwrap();
+        for config in [
+            Config {
+                cell_width: CellWidth::Bits16,
+                ..Config::default()
+            },
+            Config {
+                tape: TapeConfig {
+                    boundary: Boundary::Unbounded,
+                    ..TapeConfig::default()
+                },
+                ..Config::default()
+            },
+        ] {
+            assert!(emit_elf(&program, &config, Some(&target()), None).is_err());
+        }
+    }
+
+    /// Writes the executable for `source` to `executable`.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn build(source: &str, config: &Config, executable: &Path) {
+        use std::os::unix::fs::PermissionsExt;
+
+        use interpreter::optimize::{optimize, Passes};
+
+        let mut program = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut program, &Passes::level(2), config);
+        std::fs::write(
+            executable,
+            emit_elf(&program, config, Some(&target()), None).unwrap(),
+        )
+        .unwrap();
+        std::fs::set_permissions(executable, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn runs_samples() {
+        use crate::samples::{check_samples, run_sample};
+
+        check_samples(build);
+        let wrapping = Config {
+            eof: EofBehavior::MinusOne,
+            tape: TapeConfig {
+                length: 10,
+                boundary: Boundary::Wrap,
+            },
+            ..Config::default()
+        };
+        let output = run_sample("<+[>+<-]>.,.,.", &wrapping, b"a", build);
+        assert_eq!(output.stdout, b"\x01a\xff");
+        let output = run_sample("+++,.", &Config::default(), b"", build);
+        assert_eq!(output.stdout, b"\x03");
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn stops_off_the_tape() {
+        crate::samples::check_stops_off_the_tape(build);
+    }
+}
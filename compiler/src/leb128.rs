@@ -0,0 +1,47 @@
+//! LEB128, the variable-length integer encoding shared by WebAssembly and
+//! DWARF.
+
+/// Appends `value` in unsigned LEB128.
+pub(crate) fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Appends `value` in signed LEB128.
+pub(crate) fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        let mut out = Vec::new();
+        unsigned(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+        out.clear();
+        signed(&mut out, -123456);
+        assert_eq!(out, [0xc0, 0xbb, 0x78]);
+        out.clear();
+        signed(&mut out, 64);
+        assert_eq!(out, [0xc0, 0x00]);
+    }
+}
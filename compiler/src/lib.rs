@@ -0,0 +1,95 @@
+mod asm;
+mod c;
+mod dwarf;
+mod elf;
+mod leb128;
+mod llvm;
+mod lower;
+mod qbe;
+mod rust;
+#[cfg(test)]
+mod samples;
+mod target;
+mod toolchain;
+mod wasm;
+
+use std::str::FromStr;
+
+use anyhow::bail;
+
+pub use asm::emit_asm;
+pub use c::emit_c;
+pub use elf::emit_elf;
+pub use llvm::emit_llvm;
+pub use qbe::emit_qbe;
+pub use rust::emit_rust;
+pub use target::{Arch, ObjectFormat, SyscallAbi, Target};
+pub use toolchain::{with_suffix, Optimizer, Toolchain};
+pub use wasm::{emit_wasm, Wasm};
+
+/// How `bf -c` turns a program into an executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Emit LLVM IR and build it with an installed LLVM toolchain.
+    #[default]
+    Llvm,
+    /// Write a static x86-64 Linux executable directly.
+    Elf,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "llvm" => Ok(Backend::Llvm),
+            "elf" => Ok(Backend::Elf),
+            _ => bail!("unknown backend '{}', expected llvm or elf", s),
+        }
+    }
+}
+
+/// A source language `bf -c --emit` translates programs to instead of
+/// building an executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    C,
+    Rust,
+    /// x86-64 GNU assembler source.
+    Asm,
+    /// A binary `.wasm` module, with its text format next to it as `.wat`.
+    Wasm,
+    /// QBE intermediate language.
+    Qbe,
+}
+
+impl Emit {
+    /// The file extension of the generated source.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Emit::C => "c",
+            Emit::Rust => "rs",
+            Emit::Asm => "s",
+            Emit::Wasm => "wasm",
+            Emit::Qbe => "ssa",
+        }
+    }
+}
+
+impl FromStr for Emit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "c" => Ok(Emit::C),
+            "rust" => Ok(Emit::Rust),
+            "asm" => Ok(Emit::Asm),
+            "wasm" => Ok(Emit::Wasm),
+            "qbe" => Ok(Emit::Qbe),
+            _ => bail!(
+                "unknown source language '{}', expected c, rust, asm, wasm or qbe",
+                s
+            ),
+        }
+    }
+}
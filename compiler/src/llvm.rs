@@ -0,0 +1,778 @@
+use std::{fmt::Write, path::Path};
+
+use interpreter::{
+    config::{Boundary, CellWidth, Config, EofBehavior, TapeConfig},
+    ir::{Module, Position},
+};
+
+use crate::{
+    dwarf::split,
+    lower::{check, lower, Lower, OFF_TAPE_MESSAGE, OFF_TAPE_STATUS},
+    target::Target,
+};
+
+/// The metadata node describing `main`.
+const SUBPROGRAM: usize = 9;
+/// The location of code before the first operation.
+const ENTRY_LOCATION: usize = 17;
+/// The metadata node of the first operation's location.
+const FIRST_LOCATION: usize = 18;
+
+struct Emitter {
+    eof: EofBehavior,
+    tape: TapeConfig,
+    width: CellWidth,
+    /// The LLVM integer type of a cell, e.g. `i8`.
+    cell: String,
+    /// The LLVM integer type of the data pointer, as wide as a pointer.
+    index: String,
+    /// The types of pointers to a cell, the data pointer and the tape: `ptr`,
+    /// or e.g. `i8*` with typed pointers.
+    cell_pointer: String,
+    index_pointer: String,
+    tape_pointer: String,
+    body: String,
+    next_value: usize,
+    next_label: usize,
+    /// The current cell in a run of multiplies.
+    multiplier: String,
+    /// The source of each operation, with `-g`.
+    locations: Option<Vec<Position>>,
+    /// The location attached to new instructions.
+    location: Option<usize>,
+    /// Whether a bounds check branches to `%off_tape`.
+    off_tape: bool,
+}
+
+impl Emitter {
+    fn new(config: &Config, pointer_width: u32, typed_pointers: bool) -> Emitter {
+        let cell = format!("i{}", config.cell_width.bits());
+        let index = format!("i{}", pointer_width);
+        let pointer = |pointee: &str| {
+            if typed_pointers {
+                format!("{}*", pointee)
+            } else {
+                "ptr".to_string()
+            }
+        };
+        Emitter {
+            eof: config.eof,
+            tape: config.tape,
+            width: config.cell_width,
+            cell_pointer: pointer(&cell),
+            index_pointer: pointer(&index),
+            tape_pointer: pointer(&format!("[{} x {}]", config.tape.length, cell)),
+            cell,
+            index,
+            body: String::new(),
+            next_value: 0,
+            next_label: 0,
+            multiplier: String::new(),
+            locations: None,
+            location: None,
+            off_tape: false,
+        }
+    }
+
+    fn value(&mut self) -> String {
+        self.next_value += 1;
+        format!("%v{}", self.next_value)
+    }
+
+    fn label(&mut self) -> usize {
+        self.next_label += 1;
+        self.next_label
+    }
+
+    fn line(&mut self, line: &str) {
+        self.body.push_str("  ");
+        self.body.push_str(line);
+        if let Some(location) = self.location {
+            write!(self.body, ", !dbg !{}", location).unwrap();
+        }
+        self.body.push('\n');
+    }
+
+    fn block(&mut self, name: &str) {
+        self.body.push_str(name);
+        self.body.push_str(":\n");
+    }
+
+    /// Loads the cell at the address `cell` into `value`.
+    fn load(&mut self, value: &str, cell: &str) {
+        self.line(&format!(
+            "{} = load {}, {} {}",
+            value, self.cell, self.cell_pointer, cell
+        ));
+    }
+
+    fn store(&mut self, value: &str, cell: &str) {
+        self.line(&format!(
+            "store {} {}, {} {}",
+            self.cell, value, self.cell_pointer, cell
+        ));
+    }
+
+    fn cell(&mut self) -> String {
+        self.cell_at(0)
+    }
+
+    fn cell_at(&mut self, offset: isize) -> String {
+        let pointer = self.value();
+        let ty = self.index.clone();
+        self.line(&format!(
+            "{} = load {}, {} %ptr",
+            pointer, ty, self.index_pointer
+        ));
+        let index = self.offset(&pointer, offset);
+        let cell = self.value();
+        self.line(&format!(
+            "{} = getelementptr inbounds [{} x {}], {} @tape, {} 0, {} {}",
+            cell, self.tape.length, self.cell, self.tape_pointer, ty, ty, index
+        ));
+        cell
+    }
+
+    /// The index `offset` cells from `index`, wrapping around the tape with
+    /// [`Boundary::Wrap`] and branching to `%off_tape` if it leaves the tape
+    /// otherwise.
+    fn offset(&mut self, index: &str, offset: isize) -> String {
+        if offset == 0 {
+            return index.to_string();
+        }
+        let moved = self.value();
+        let ty = self.index.clone();
+        self.line(&format!("{} = add {} {}, {}", moved, ty, index, offset));
+        if self.tape.boundary != Boundary::Wrap {
+            // Moves left of cell 0 wrap around to large unsigned indices.
+            let inside = self.value();
+            let label = self.label();
+            self.line(&format!(
+                "{} = icmp ult {} {}, {}",
+                inside, ty, moved, self.tape.length
+            ));
+            self.line(&format!(
+                "br i1 {}, label %in_bounds{}, label %off_tape",
+                inside, label
+            ));
+            self.block(&format!("in_bounds{}", label));
+            self.off_tape = true;
+            return moved;
+        }
+        // Both operands are below the length, so the sum cannot overflow.
+        let wrapped = self.value();
+        self.line(&format!(
+            "{} = urem {} {}, {}",
+            wrapped, ty, moved, self.tape.length
+        ));
+        wrapped
+    }
+
+    /// Converts a cell value to the `int` passed to `putchar`, keeping the
+    /// low 8 bits.
+    fn char_out(&mut self, value: &str) -> String {
+        let mut byte = value.to_string();
+        if self.width != CellWidth::Bits8 {
+            byte = self.value();
+            let ty = self.cell.clone();
+            self.line(&format!("{} = trunc {} {} to i8", byte, ty, value));
+        }
+        let wide = self.value();
+        self.line(&format!("{} = zext i8 {} to i32", wide, byte));
+        wide
+    }
+
+    /// Converts a byte returned by `getchar` to a cell value.
+    fn char_in(&mut self, wide: &str) -> String {
+        let byte = self.value();
+        self.line(&format!("{} = trunc i32 {} to i8", byte, wide));
+        if self.width == CellWidth::Bits8 {
+            return byte;
+        }
+        let value = self.value();
+        let ty = self.cell.clone();
+        self.line(&format!("{} = zext i8 {} to {}", value, byte, ty));
+        value
+    }
+}
+
+impl Lower for Emitter {
+    fn locate(&mut self, position: Position) {
+        if let Some(locations) = &mut self.locations {
+            self.location = Some(FIRST_LOCATION + locations.len());
+            locations.push(position);
+        }
+    }
+
+    fn add(&mut self, amount: i64) {
+        let cell = self.cell();
+        let old = self.value();
+        let new = self.value();
+        let ty = self.cell.clone();
+        self.load(&old, &cell);
+        self.line(&format!("{} = add {} {}, {}", new, ty, old, amount));
+        self.store(&new, &cell);
+    }
+
+    fn shift(&mut self, amount: isize) {
+        let old = self.value();
+        let ty = self.index.clone();
+        self.line(&format!(
+            "{} = load {}, {} %ptr",
+            old, ty, self.index_pointer
+        ));
+        let new = self.offset(&old, amount);
+        self.line(&format!(
+            "store {} {}, {} %ptr",
+            ty, new, self.index_pointer
+        ));
+    }
+
+    fn output(&mut self) {
+        let cell = self.cell();
+        let value = self.value();
+        self.load(&value, &cell);
+        let wide = self.char_out(&value);
+        let ret = self.value();
+        self.line(&format!("{} = call i32 @putchar(i32 {})", ret, wide));
+    }
+
+    fn input(&mut self) {
+        let cell = self.cell();
+        let wide = self.value();
+        let ty = self.cell.clone();
+        self.line(&format!("{} = call i32 @getchar()", wide));
+        let eof = self.value();
+        self.line(&format!("{} = icmp eq i32 {}, -1", eof, wide));
+        match self.eof {
+            EofBehavior::Unchanged => {
+                let label = self.label();
+                self.line(&format!(
+                    "br i1 {}, label %read_done{}, label %read_store{}",
+                    eof, label, label
+                ));
+                self.block(&format!("read_store{}", label));
+                let value = self.char_in(&wide);
+                self.store(&value, &cell);
+                self.line(&format!("br label %read_done{}", label));
+                self.block(&format!("read_done{}", label));
+            }
+            EofBehavior::Zero | EofBehavior::MinusOne => {
+                let on_eof = if self.eof == EofBehavior::Zero { 0 } else { -1 };
+                let value = self.char_in(&wide);
+                let stored = self.value();
+                self.line(&format!(
+                    "{} = select i1 {}, {} {}, {} {}",
+                    stored, eof, ty, on_eof, ty, value
+                ));
+                self.store(&stored, &cell);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        let cell = self.cell();
+        self.store("0", &cell);
+    }
+
+    fn loop_start(&mut self, label: usize, _: Position) {
+        self.line(&format!("br label %loop_head{}", label));
+        self.block(&format!("loop_head{}", label));
+        let cell = self.cell();
+        let value = self.value();
+        let zero = self.value();
+        let ty = self.cell.clone();
+        self.load(&value, &cell);
+        self.line(&format!("{} = icmp eq {} {}, 0", zero, ty, value));
+        self.line(&format!(
+            "br i1 {}, label %loop_end{}, label %loop_body{}",
+            zero, label, label
+        ));
+        self.block(&format!("loop_body{}", label));
+    }
+
+    fn loop_end(&mut self, label: usize) {
+        self.line(&format!("br label %loop_head{}", label));
+        self.block(&format!("loop_end{}", label));
+    }
+
+    fn mul_start(&mut self, label: usize) {
+        let cell = self.cell();
+        let value = self.value();
+        let zero = self.value();
+        let ty = self.cell.clone();
+        self.load(&value, &cell);
+        self.line(&format!("{} = icmp eq {} {}, 0", zero, ty, value));
+        self.line(&format!(
+            "br i1 {}, label %mul_done{}, label %mul_body{}",
+            zero, label, label
+        ));
+        self.block(&format!("mul_body{}", label));
+        self.multiplier = value;
+    }
+
+    fn mul(&mut self, offset: isize, factor: i64) {
+        let target = self.cell_at(offset);
+        let old = self.value();
+        let product = self.value();
+        let new = self.value();
+        let ty = self.cell.clone();
+        self.load(&old, &target);
+        self.line(&format!(
+            "{} = mul {} {}, {}",
+            product, ty, self.multiplier, factor
+        ));
+        self.line(&format!("{} = add {} {}, {}", new, ty, old, product));
+        self.store(&new, &target);
+    }
+
+    fn mul_end(&mut self, label: usize) {
+        self.line(&format!("br label %mul_done{}", label));
+        self.block(&format!("mul_done{}", label));
+    }
+}
+
+/// Quotes `value` as an LLVM string.
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", escaped(value))
+}
+
+/// `value` with quotes, backslashes and control and non-ASCII bytes written
+/// as `\XX` escapes, so it fits on one line of IR.
+fn escaped(value: &str) -> String {
+    let mut escaped = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' | 0..=0x1f | 0x7f.. => write!(escaped, "\\{:02X}", byte).unwrap(),
+            _ => escaped.push(byte as char),
+        }
+    }
+    escaped
+}
+
+/// Writes the metadata describing `source` and the locations in `emitter`.
+fn debug_info(
+    ir: &mut String,
+    source: &Path,
+    pointer_width: u32,
+    emitter: &Emitter,
+) -> std::fmt::Result {
+    let (name, directory) = split(source);
+    let bits = emitter.width.bits();
+    let nodes = [
+        "distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: \"bf\", \
+         isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug, globals: !2)"
+            .to_string(),
+        format!(
+            "!DIFile(filename: {}, directory: {})",
+            quoted(&name),
+            quoted(&directory)
+        ),
+        "!{!3}".to_string(),
+        "!DIGlobalVariableExpression(var: !4, expr: !DIExpression())".to_string(),
+        "distinct !DIGlobalVariable(name: \"tape\", scope: !0, file: !1, line: 1, type: !5, \
+         isLocal: true, isDefinition: true)"
+            .to_string(),
+        format!(
+            "!DICompositeType(tag: DW_TAG_array_type, baseType: !6, size: {}, elements: !7)",
+            bits as usize * emitter.tape.length
+        ),
+        format!(
+            "!DIBasicType(name: \"cell\", size: {}, encoding: DW_ATE_unsigned)",
+            bits
+        ),
+        "!{!8}".to_string(),
+        format!("!DISubrange(count: {})", emitter.tape.length),
+        "distinct !DISubprogram(name: \"main\", scope: !1, file: !1, line: 1, type: !10, \
+         scopeLine: 1, spFlags: DISPFlagDefinition, unit: !0)"
+            .to_string(),
+        "!DISubroutineType(types: !11)".to_string(),
+        "!{!12}".to_string(),
+        "!DIBasicType(name: \"int\", size: 32, encoding: DW_ATE_signed)".to_string(),
+        "!DILocalVariable(name: \"ptr\", scope: !9, file: !1, line: 1, type: !14)".to_string(),
+        format!(
+            "!DIBasicType(name: \"size_t\", size: {}, encoding: DW_ATE_unsigned)",
+            pointer_width
+        ),
+        "!{i32 7, !\"Dwarf Version\", i32 4}".to_string(),
+        "!{i32 2, !\"Debug Info Version\", i32 3}".to_string(),
+    ];
+    writeln!(ir)?;
+    writeln!(ir, "!llvm.dbg.cu = !{{!0}}")?;
+    writeln!(ir, "!llvm.module.flags = !{{!15, !16}}")?;
+    writeln!(ir)?;
+    for (index, node) in nodes.iter().enumerate() {
+        writeln!(ir, "!{} = {}", index, node)?;
+    }
+    let entry = Position { line: 1, column: 1 };
+    let locations = emitter.locations.iter().flatten();
+    for (index, position) in std::iter::once(&entry).chain(locations).enumerate() {
+        writeln!(
+            ir,
+            "!{} = !DILocation(line: {}, column: {}, scope: !{})",
+            ENTRY_LOCATION + index,
+            position.line,
+            position.column,
+            SUBPROGRAM
+        )?;
+    }
+    Ok(())
+}
+
+/// Lowers a BF module to a textual LLVM IR module.
+///
+/// The module defines `main`, keeps the tape in the `@tape` global and the
+/// data pointer in a stack slot, and performs I/O through libc's
+/// `putchar`/`getchar`. On end of input `,` follows `config.eof`. Unless the
+/// tape wraps, every move is checked, and one off the tape writes a message
+/// to stderr and exits with status 5.
+///
+/// The module is stamped with the triple and data layout of `target`. Without
+/// one, LLVM picks its default triple and the data pointer is 64 bits wide.
+///
+/// With `debug`, the path of the source file, every instruction carries the
+/// line and column of the operation it implements, and the tape and data
+/// pointer are described as the variables `tape` and `ptr`.
+///
+/// With `typed_pointers`, pointer types name their pointee, as in `i8*`, for
+/// LLVM 14 and earlier, whose opaque pointers are experimental and crash
+/// some optimizations.
+pub fn emit_llvm(
+    program: &Module,
+    module: &str,
+    config: &Config,
+    target: Option<&Target>,
+    debug: Option<&Path>,
+    typed_pointers: bool,
+) -> anyhow::Result<String> {
+    check(config)?;
+    let pointer_width = target.map_or(64, |target| target.pointer_width);
+    let mut emitter = Emitter::new(config, pointer_width, typed_pointers);
+    if debug.is_some() {
+        emitter.locations = Some(Vec::new());
+        emitter.location = Some(ENTRY_LOCATION);
+    }
+
+    let alloca = format!("%ptr = alloca {}", emitter.index);
+    emitter.line(&alloca);
+    if debug.is_some() {
+        let declare = format!(
+            "call void @llvm.dbg.declare(metadata {} %ptr, metadata !13, metadata !DIExpression())",
+            emitter.index_pointer
+        );
+        emitter.line(&declare);
+    }
+    let store = format!("store {} 0, {} %ptr", emitter.index, emitter.index_pointer);
+    emitter.line(&store);
+    lower(&program.body, config, &mut emitter);
+    emitter.line("ret i32 0");
+    let message = OFF_TAPE_MESSAGE.len();
+    let byte_pointer = if typed_pointers { "i8*" } else { "ptr" };
+    if emitter.off_tape {
+        let index = emitter.index.clone();
+        let text = if typed_pointers {
+            format!(
+                "i8* getelementptr inbounds ([{} x i8], [{} x i8]* @off_tape, i64 0, i64 0)",
+                message, message
+            )
+        } else {
+            "ptr @off_tape".to_string()
+        };
+        emitter.location = emitter.location.map(|_| ENTRY_LOCATION);
+        emitter.block("off_tape");
+        let written = emitter.value();
+        emitter.line(&format!(
+            "{} = call {} @write(i32 2, {}, {} {})",
+            written, index, text, index, message
+        ));
+        emitter.line(&format!("call void @exit(i32 {})", OFF_TAPE_STATUS));
+        emitter.line("unreachable");
+    }
+
+    let mut ir = String::new();
+    writeln!(ir, "; ModuleID = '{}'", escaped(module))?;
+    writeln!(ir, "source_filename = {}", quoted(module))?;
+    if let Some(target) = target {
+        writeln!(ir, "target datalayout = \"{}\"", target.data_layout)?;
+        writeln!(ir, "target triple = \"{}\"", target.triple)?;
+    }
+    writeln!(ir)?;
+    write!(
+        ir,
+        "@tape = internal global [{} x {}] zeroinitializer",
+        emitter.tape.length, emitter.cell
+    )?;
+    if debug.is_some() {
+        write!(ir, ", !dbg !3")?;
+    }
+    writeln!(ir)?;
+    if emitter.off_tape {
+        writeln!(
+            ir,
+            "@off_tape = private unnamed_addr constant [{} x i8] c{}",
+            message,
+            quoted(OFF_TAPE_MESSAGE)
+        )?;
+    }
+    writeln!(ir)?;
+    writeln!(ir, "declare i32 @putchar(i32)")?;
+    writeln!(ir, "declare i32 @getchar()")?;
+    if emitter.off_tape {
+        writeln!(
+            ir,
+            "declare {} @write(i32, {}, {})",
+            emitter.index, byte_pointer, emitter.index
+        )?;
+        writeln!(ir, "declare void @exit(i32)")?;
+    }
+    if debug.is_some() {
+        writeln!(
+            ir,
+            "declare void @llvm.dbg.declare(metadata, metadata, metadata)"
+        )?;
+    }
+    writeln!(ir)?;
+    if debug.is_some() {
+        writeln!(ir, "define i32 @main() !dbg !{} {{", SUBPROGRAM)?;
+    } else {
+        writeln!(ir, "define i32 @main() {{")?;
+    }
+    writeln!(ir, "entry:")?;
+    ir.push_str(&emitter.body);
+    writeln!(ir, "}}")?;
+    if let Some(source) = debug {
+        debug_info(&mut ir, source, pointer_width, &emitter)?;
+    }
+    Ok(ir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interpreter::{
+        config::Overflow,
+        ir::{Node, Op},
+    };
+
+    fn target() -> Target {
+        "x86_64-unknown-linux-gnu".parse().unwrap()
+    }
+
+    #[test]
+    fn module_layout() {
+        let program = Module::parse(&"+[->.<],".chars().collect::<Vec<_>>()).unwrap();
+        let ir = emit_llvm(
+            &program,
+            "t.bf",
+            &Config::default(),
+            Some(&target()),
+            None,
+            false,
+        )
+        .unwrap();
+        assert!(ir.contains("@tape = internal global [30000 x i8] zeroinitializer"));
+        assert!(ir.contains("define i32 @main() {"));
+        assert!(ir.contains("call i32 @putchar"));
+        assert!(ir.contains("call i32 @getchar()"));
+        assert!(ir.contains("loop_head1:"));
+        assert!(ir.contains("loop_end1:"));
+        assert!(ir.contains(
+            "%v14 = add i64 %v13, 1
+  %v15 = icmp ult i64 %v14, 30000
+  br i1 %v15, label %in_bounds1, label %off_tape
+in_bounds1:
+  store i64 %v14, ptr %ptr
+"
+        ));
+        assert!(ir.contains("declare i64 @write(i32, ptr, i64)\n"));
+        assert!(ir.contains("ret i32 0\noff_tape:\n"));
+        assert!(ir.trim_end().ends_with(
+            "call i64 @write(i32 2, ptr @off_tape, i64 27)
+  call void @exit(i32 5)
+  unreachable
+}"
+        ));
+    }
+
+    #[test]
+    fn escaped_module_name() {
+        let program = Module::parse(&['+']).unwrap();
+        let ir = emit_llvm(
+            &program,
+            "a \"b\"\\c\n.bf",
+            &Config::default(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        assert!(ir.starts_with(
+            "; ModuleID = 'a \\22b\\22\\5Cc\\0A.bf'\nsource_filename = \"a \\22b\\22\\5Cc\\0A.bf\"\n"
+        ));
+    }
+
+    #[test]
+    fn debug_info() {
+        let program = Module::parse(&"+\n .".chars().collect::<Vec<_>>()).unwrap();
+        let source = Path::new("/src/t \"1\".bf");
+        let ir = emit_llvm(
+            &program,
+            "t.bf",
+            &Config::default(),
+            Some(&target()),
+            Some(source),
+            false,
+        )
+        .unwrap();
+        assert!(ir.contains("zeroinitializer, !dbg !3\n"));
+        assert!(ir.contains("define i32 @main() !dbg !9 {"));
+        assert!(ir.contains("%ptr = alloca i64, !dbg !17\n"));
+        assert!(ir.contains("%v4 = add i8 %v3, 1, !dbg !18\n"));
+        assert!(ir.contains("call i32 @putchar(i32 %v8), !dbg !19\n"));
+        assert!(ir.contains("!1 = !DIFile(filename: \"t \\221\\22.bf\", directory: \"/src\")"));
+        assert!(ir.contains("!DISubrange(count: 30000)"));
+        assert!(ir.contains("!19 = !DILocation(line: 2, column: 2, scope: !9)"));
+    }
+
+    #[test]
+    fn target_layout() {
+        let program = Module::parse(&">".chars().collect::<Vec<_>>()).unwrap();
+        let ir = emit_llvm(
+            &program,
+            "t.bf",
+            &Config::default(),
+            Some(&target()),
+            None,
+            false,
+        )
+        .unwrap();
+        assert!(ir.contains("target triple = \"x86_64-unknown-linux-gnu\"\n"));
+        assert!(ir.contains("target datalayout = \"e-m:e-p270:32:32-"));
+        assert!(ir.contains("%ptr = alloca i64\n"));
+
+        let target = "wasm32-unknown-unknown".parse().unwrap();
+        let ir = emit_llvm(
+            &program,
+            "t.bf",
+            &Config::default(),
+            Some(&target),
+            None,
+            false,
+        )
+        .unwrap();
+        assert!(ir.contains("target triple = \"wasm32-unknown-unknown\"\n"));
+        assert!(ir.contains("%ptr = alloca i32\n"));
+        assert!(ir.contains("%v2 = add i32 %v1, 1\n  %v3 = icmp ult i32 %v2, 30000\n"));
+        assert!(ir.contains("declare i32 @write(i32, ptr, i32)\n"));
+
+        let ir = emit_llvm(&program, "t.bf", &Config::default(), None, None, false).unwrap();
+        assert!(!ir.contains("target "));
+        assert!(ir.contains("%ptr = alloca i64\n"));
+    }
+
+    #[test]
+    fn typed_pointers() {
+        let program = Module::parse(&">+".chars().collect::<Vec<_>>()).unwrap();
+        let ir = emit_llvm(
+            &program,
+            "t.bf",
+            &Config::default(),
+            Some(&target()),
+            None,
+            true,
+        )
+        .unwrap();
+        assert!(!ir.contains(" ptr "));
+        assert!(ir.contains("store i64 0, i64* %ptr\n"));
+        assert!(ir.contains(
+            "%v5 = getelementptr inbounds [30000 x i8], [30000 x i8]* @tape, i64 0, i64 %v4\n"
+        ));
+        assert!(ir.contains("%v6 = load i8, i8* %v5\n"));
+        assert!(ir.contains("store i8 %v7, i8* %v5\n"));
+        assert!(ir.contains("declare i64 @write(i32, i8*, i64)\n"));
+        assert!(ir.contains(
+            "call i64 @write(i32 2, i8* getelementptr inbounds ([27 x i8], [27 x i8]* @off_tape, i64 0, i64 0), i64 27)"
+        ));
+    }
+
+    #[test]
+    fn eof_behavior() {
+        let program = Module::parse(&[',']).unwrap();
+        let config = Config {
+            eof: EofBehavior::Zero,
+            ..Config::default()
+        };
+        let ir = emit_llvm(&program, "t.bf", &config, Some(&target()), None, false).unwrap();
+        assert!(ir.contains("%v5 = trunc i32 %v3 to i8"));
+        assert!(ir.contains("select i1 %v4, i8 0, i8 %v5"));
+    }
+
+    #[test]
+    fn wide_cells() {
+        let program = Module::parse(&"-.,".chars().collect::<Vec<_>>()).unwrap();
+        let config = Config {
+            cell_width: CellWidth::Bits32,
+            eof: EofBehavior::MinusOne,
+            ..Config::default()
+        };
+        let ir = emit_llvm(&program, "t.bf", &config, Some(&target()), None, false).unwrap();
+        assert!(ir.contains("@tape = internal global [30000 x i32] zeroinitializer"));
+        assert!(ir.contains("add i32 %v3, -1"));
+        assert!(ir.contains("trunc i32 %v7 to i8"));
+        assert!(ir.contains("select i1 %v14, i32 -1, i32 %v16"));
+    }
+
+    #[test]
+    fn only_wrapping_cells() {
+        let program = Module::parse(&['+']).unwrap();
+        let config = Config {
+            overflow: Overflow::Error,
+            ..Config::default()
+        };
+        assert!(emit_llvm(&program, "t.bf", &config, Some(&target()), None, false).is_err());
+    }
+
+    #[test]
+    fn wrapping_tape() {
+        let program = Module::parse(&"<+>".chars().collect::<Vec<_>>()).unwrap();
+        let config = Config {
+            tape: TapeConfig {
+                length: 100,
+                boundary: Boundary::Wrap,
+            },
+            ..Config::default()
+        };
+        let ir = emit_llvm(&program, "t.bf", &config, Some(&target()), None, false).unwrap();
+        assert!(ir.contains("@tape = internal global [100 x i8] zeroinitializer"));
+        assert!(ir.contains("%v2 = add i64 %v1, 99\n  %v3 = urem i64 %v2, 100"));
+
+        let config = Config {
+            tape: TapeConfig {
+                boundary: Boundary::Grow,
+                ..TapeConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(emit_llvm(&program, "t.bf", &config, Some(&target()), None, false).is_err());
+    }
+
+    #[test]
+    fn folded_amounts_wrap() {
+        let program = Module {
+            body: vec![Node::new(
+                Op::Add(257),
+                interpreter::ir::Position { line: 1, column: 1 },
+            )],
+        };
+        let ir = emit_llvm(
+            &program,
+            "t.bf",
+            &Config::default(),
+            Some(&target()),
+            None,
+            false,
+        )
+        .unwrap();
+        assert!(ir.contains("add i8 %v3, 1"));
+    }
+}
@@ -0,0 +1,141 @@
+//! The lowering shared by the LLVM, C, Rust, QBE and assembler emitters.
+//!
+//! [`lower`] walks the IR and calls a [`Lower`] implementation with operands
+//! already normalized, so every emitter agrees on what a program means:
+//! amounts and factors are wrapped to the cell width, offsets on a wrapping
+//! tape are reduced to `0..length`, labels are numbered in source order and
+//! runs of multiplies are guarded by a single test of the current cell.
+
+use anyhow::bail;
+use interpreter::{
+    config::{Boundary, CellWidth, Config, Overflow},
+    ir::{Node, Op, Position},
+};
+
+/// What a compiled program writes to stderr when it moves off a tape that
+/// does not wrap, before exiting with [`OFF_TAPE_STATUS`].
+pub(crate) const OFF_TAPE_MESSAGE: &str = "pointer moved off the tape\n";
+/// The exit status of a program that moved off the tape, the same as `bf`'s
+/// own for runtime errors.
+pub(crate) const OFF_TAPE_STATUS: i32 = 5;
+
+/// Target-specific code for each operation.
+pub(crate) trait Lower {
+    /// Marks the source of the operations that follow.
+    fn locate(&mut self, _position: Position) {}
+    /// Adds `amount`, wrapped to the cell width, to the current cell.
+    fn add(&mut self, amount: i64);
+    /// Moves the pointer by `offset`, which is in `0..length` if the tape
+    /// wraps.
+    fn shift(&mut self, offset: isize);
+    fn output(&mut self);
+    fn input(&mut self);
+    fn clear(&mut self);
+    /// Opens a loop that runs while the current cell is nonzero.
+    fn loop_start(&mut self, label: usize, position: Position);
+    fn loop_end(&mut self, label: usize);
+
+    /// Moves by `stride` until the current cell is zero.
+    fn scan(&mut self, label: usize, position: Position, stride: isize) {
+        self.loop_start(label, position);
+        self.shift(stride);
+        self.loop_end(label);
+    }
+
+    /// Opens a run of multiplies, which only runs if the current cell is
+    /// nonzero.
+    fn mul_start(&mut self, label: usize);
+    /// Adds the current cell times `factor` to the cell at `offset`, both
+    /// normalized like the operands of [`add`](Lower::add) and
+    /// [`shift`](Lower::shift).
+    fn mul(&mut self, offset: isize, factor: i64);
+    fn mul_end(&mut self, label: usize);
+}
+
+/// Rejects settings that compiled programs do not support.
+pub(crate) fn check(config: &Config) -> anyhow::Result<()> {
+    if config.overflow != Overflow::Wrap {
+        bail!("compiled cells always wrap; use --overflow wrap or interpret the program");
+    }
+    if matches!(config.tape.boundary, Boundary::Grow | Boundary::Unbounded) {
+        bail!("compiled tapes cannot grow; use --tape-boundary error or wrap");
+    }
+    Ok(())
+}
+
+/// Whether `body` reads input anywhere.
+pub(crate) fn uses_input(body: &[Node]) -> bool {
+    body.iter().any(|node| match &node.op {
+        Op::Input => true,
+        Op::Loop(inner) => uses_input(inner),
+        _ => false,
+    })
+}
+
+/// Lowers `body` into `target`.
+pub(crate) fn lower(body: &[Node], config: &Config, target: &mut impl Lower) {
+    let mut lowering = Lowering {
+        width: config.cell_width,
+        wrap: (config.tape.boundary == Boundary::Wrap).then_some(config.tape.length as isize),
+        next_label: 0,
+    };
+    lowering.body(body, target);
+}
+
+struct Lowering {
+    width: CellWidth,
+    /// The tape length, if moves wrap around it.
+    wrap: Option<isize>,
+    next_label: usize,
+}
+
+impl Lowering {
+    fn label(&mut self) -> usize {
+        self.next_label += 1;
+        self.next_label
+    }
+
+    fn offset(&self, offset: isize) -> isize {
+        match self.wrap {
+            Some(length) => offset.rem_euclid(length),
+            None => offset,
+        }
+    }
+
+    fn body(&mut self, body: &[Node], target: &mut impl Lower) {
+        let mut mul = None;
+        for (index, node) in body.iter().enumerate() {
+            target.locate(node.position);
+            match &node.op {
+                Op::Add(amount) => target.add(self.width.wrap(*amount)),
+                Op::Move(offset) => target.shift(self.offset(*offset)),
+                Op::Output => target.output(),
+                Op::Input => target.input(),
+                Op::Loop(inner) => {
+                    let label = self.label();
+                    target.loop_start(label, node.position);
+                    self.body(inner, target);
+                    target.loop_end(label);
+                }
+                Op::Clear => target.clear(),
+                Op::Scan(stride) => {
+                    let label = self.label();
+                    target.scan(label, node.position, self.offset(*stride));
+                }
+                Op::Mul { offset, factor } => {
+                    let label = *mul.get_or_insert_with(|| {
+                        let label = self.label();
+                        target.mul_start(label);
+                        label
+                    });
+                    target.mul(self.offset(*offset), self.width.wrap(*factor));
+                    let next = body.get(index + 1).map(|node| &node.op);
+                    if !matches!(next, Some(Op::Mul { .. })) {
+                        target.mul_end(label);
+                        mul = None;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use std::fmt::Write;
+
+use interpreter::{
+    config::{Boundary, CellWidth, Config, EofBehavior, TapeConfig},
+    ir::{Module, Position},
+};
+
+use crate::lower::{check, lower, Lower, OFF_TAPE_MESSAGE, OFF_TAPE_STATUS};
+
+struct Emitter {
+    eof: EofBehavior,
+    tape: TapeConfig,
+    width: CellWidth,
+    body: String,
+    next_label: usize,
+    /// Whether any check jumps to `@off_tape`.
+    off_tape: bool,
+}
+
+impl Emitter {
+    fn label(&mut self) -> usize {
+        self.next_label += 1;
+        self.next_label
+    }
+
+    fn line(&mut self, line: &str) {
+        self.body.push('\t');
+        self.body.push_str(line);
+        self.body.push('\n');
+    }
+
+    fn block(&mut self, name: &str) {
+        self.body.push('@');
+        self.body.push_str(name);
+        self.body.push('\n');
+    }
+
+    /// The QBE type cells are computed in, `w` or `l` for 64-bit cells.
+    fn ty(&self) -> char {
+        if self.width == CellWidth::Bits64 {
+            'l'
+        } else {
+            'w'
+        }
+    }
+
+    fn bytes(&self) -> isize {
+        self.width.bits() as isize / 8
+    }
+
+    fn load(&mut self, value: &str, address: &str) {
+        let load = match self.width {
+            CellWidth::Bits8 => "loadub",
+            CellWidth::Bits16 => "loaduh",
+            CellWidth::Bits32 => "loadw",
+            CellWidth::Bits64 => "loadl",
+        };
+        let line = format!("{} ={} {} {}", value, self.ty(), load, address);
+        self.line(&line);
+    }
+
+    fn store(&mut self, value: &str, address: &str) {
+        let store = match self.width {
+            CellWidth::Bits8 => "storeb",
+            CellWidth::Bits16 => "storeh",
+            CellWidth::Bits32 => "storew",
+            CellWidth::Bits64 => "storel",
+        };
+        self.line(&format!("{} {}, {}", store, value, address));
+    }
+
+    /// Computes the address `offset` cells from `%p` into `target`, wrapping
+    /// around the tape with [`Boundary::Wrap`].
+    fn offset(&mut self, target: &str, offset: isize) {
+        let bytes = offset * self.bytes();
+        if self.tape.boundary != Boundary::Wrap {
+            self.line(&format!("{} =l add %p, {}", target, bytes));
+            return;
+        }
+        // Both operands are below the tape size, so the sum cannot overflow.
+        let size = self.tape.length as isize * self.bytes();
+        self.line(&format!("{} =l sub %p, $tape", target));
+        self.line(&format!("{} =l add {}, {}", target, target, bytes));
+        self.line(&format!("{} =l urem {}, {}", target, target, size));
+        self.line(&format!("{} =l add {}, $tape", target, target));
+    }
+
+    /// Jumps to `@off_tape` unless `address` is on the tape, with
+    /// [`Boundary::Error`]. Addresses left of the tape become large unsigned
+    /// indices.
+    fn check_bounds(&mut self, address: &str) {
+        if self.tape.boundary != Boundary::Error {
+            return;
+        }
+        let label = self.label();
+        let size = self.tape.length as isize * self.bytes();
+        self.line(&format!("%i =l sub {}, $tape", address));
+        self.line(&format!("%b =w cultl %i, {}", size));
+        self.line(&format!("jnz %b, @in_bounds{}, @off_tape", label));
+        self.block(&format!("in_bounds{}", label));
+        self.off_tape = true;
+    }
+
+    /// The address of the cell `offset` cells from `%p`.
+    fn address(&mut self, offset: isize) -> &'static str {
+        if offset == 0 {
+            return "%p";
+        }
+        self.offset("%a", offset);
+        "%a"
+    }
+
+    /// Branches on whether the current cell, loaded into `value`, is
+    /// nonzero.
+    fn branch(&mut self, value: &str, nonzero: &str, zero: &str) {
+        self.load(value, "%p");
+        let mut test = value.to_string();
+        if self.width == CellWidth::Bits64 {
+            // `jnz` only tests the low 32 bits of its operand.
+            self.line(&format!("%t =w cnel {}, 0", value));
+            test = "%t".to_string();
+        }
+        self.line(&format!("jnz {}, @{}, @{}", test, nonzero, zero));
+    }
+}
+
+impl Lower for Emitter {
+    fn add(&mut self, amount: i64) {
+        self.load("%v", "%p");
+        let line = format!("%v ={} add %v, {}", self.ty(), amount);
+        self.line(&line);
+        self.store("%v", "%p");
+    }
+
+    fn shift(&mut self, offset: isize) {
+        self.offset("%p", offset);
+        self.check_bounds("%p");
+    }
+
+    fn output(&mut self) {
+        self.load("%v", "%p");
+        // `putchar` converts its argument to `unsigned char`, and an `l`
+        // operand passed as `w` keeps its low 32 bits.
+        self.line("call $putchar(w %v)");
+    }
+
+    fn input(&mut self) {
+        let label = self.label();
+        self.line("%c =w call $getchar()");
+        self.line("%e =w ceqw %c, -1");
+        let eof = match self.eof {
+            EofBehavior::Unchanged => None,
+            EofBehavior::Zero => Some(0),
+            EofBehavior::MinusOne => Some(-1),
+        };
+        match eof {
+            None => self.line(&format!(
+                "jnz %e, @read_done{}, @read_store{}",
+                label, label
+            )),
+            Some(value) => {
+                self.line(&format!("jnz %e, @read_eof{}, @read_store{}", label, label));
+                self.block(&format!("read_eof{}", label));
+                self.line(&format!("%c =w copy {}", value));
+            }
+        }
+        self.block(&format!("read_store{}", label));
+        if self.width == CellWidth::Bits64 {
+            // Sign extension keeps bytes positive and turns -1 into all ones.
+            self.line("%x =l extsw %c");
+            self.store("%x", "%p");
+        } else {
+            self.store("%c", "%p");
+        }
+        if eof.is_none() {
+            self.block(&format!("read_done{}", label));
+        }
+    }
+
+    fn clear(&mut self) {
+        self.store("0", "%p");
+    }
+
+    fn loop_start(&mut self, label: usize, _: Position) {
+        self.block(&format!("loop_head{}", label));
+        self.branch(
+            "%v",
+            &format!("loop_body{}", label),
+            &format!("loop_end{}", label),
+        );
+        self.block(&format!("loop_body{}", label));
+    }
+
+    fn loop_end(&mut self, label: usize) {
+        self.line(&format!("jmp @loop_head{}", label));
+        self.block(&format!("loop_end{}", label));
+    }
+
+    fn mul_start(&mut self, label: usize) {
+        self.branch(
+            "%m",
+            &format!("mul_body{}", label),
+            &format!("mul_done{}", label),
+        );
+        self.block(&format!("mul_body{}", label));
+    }
+
+    fn mul(&mut self, offset: isize, factor: i64) {
+        let address = self.address(offset);
+        self.check_bounds(address);
+        let ty = self.ty();
+        self.load("%v", address);
+        self.line(&format!("%x ={} mul %m, {}", ty, factor));
+        self.line(&format!("%v ={} add %v, %x", ty));
+        self.store("%v", address);
+    }
+
+    fn mul_end(&mut self, label: usize) {
+        self.block(&format!("mul_done{}", label));
+    }
+}
+
+/// Lowers a BF module to a QBE intermediate language file.
+///
+/// The tape is the `$tape` data definition and `%p` holds the address of the
+/// current cell. Like the LLVM module, the file exports `main` and performs
+/// I/O through libc's `putchar`/`getchar`; on end of input `,` follows
+/// `config.eof`. Temporaries are reassigned freely, which QBE turns into SSA
+/// form itself. Unless the tape wraps, every move is checked, and one off the
+/// tape writes a message to stderr and returns 5 from `main`.
+pub fn emit_qbe(program: &Module, module: &str, config: &Config) -> anyhow::Result<String> {
+    check(config)?;
+    let mut emitter = Emitter {
+        eof: config.eof,
+        tape: config.tape,
+        width: config.cell_width,
+        body: String::new(),
+        next_label: 0,
+        off_tape: false,
+    };
+    emitter.block("start");
+    emitter.line("%p =l copy $tape");
+    lower(&program.body, config, &mut emitter);
+    emitter.line("ret 0");
+    if emitter.off_tape {
+        emitter.block("off_tape");
+        emitter.line(&format!(
+            "call $write(w 2, l $off_tape_message, l {})",
+            OFF_TAPE_MESSAGE.len()
+        ));
+        emitter.line(&format!("ret {}", OFF_TAPE_STATUS));
+    }
+
+    let mut qbe = String::new();
+    writeln!(qbe, "# Generated by bf from {}.", module)?;
+    writeln!(qbe)?;
+    writeln!(
+        qbe,
+        "data $tape = align 8 {{ z {} }}",
+        config.tape.length as isize * emitter.bytes()
+    )?;
+    writeln!(qbe)?;
+    if emitter.off_tape {
+        writeln!(
+            qbe,
+            "data $off_tape_message = {{ b \"{}\\n\" }}",
+            OFF_TAPE_MESSAGE.trim_end()
+        )?;
+        writeln!(qbe)?;
+    }
+    writeln!(qbe, "export function w $main() {{")?;
+    qbe.push_str(&emitter.body);
+    writeln!(qbe, "}}")?;
+    Ok(qbe)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command};
+
+    use super::*;
+    use crate::samples::{check_samples, check_stops_off_the_tape, installed};
+    use interpreter::optimize::{optimize, Passes};
+
+    fn emit(source: &str, config: &Config) -> String {
+        let mut program = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut program, &Passes::level(2), config);
+        emit_qbe(&program, "t.bf", config).unwrap()
+    }
+
+    #[test]
+    fn function_layout() {
+        let qbe = emit("+[->+++>-<<]>.[<],", &Config::default());
+        assert!(qbe.starts_with("# Generated by bf from t.bf.\n"));
+        assert!(qbe.contains("data $tape = align 8 { z 30000 }\n"));
+        assert!(qbe.contains("data $off_tape_message = { b \"pointer moved off the tape\\n\" }\n"));
+        assert!(qbe.contains(
+            "export function w $main() {
+@start
+\t%p =l copy $tape
+\t%v =w loadub %p
+\t%v =w add %v, 1
+\tstoreb %v, %p
+\t%m =w loadub %p
+\tjnz %m, @mul_body1, @mul_done1
+@mul_body1
+\t%a =l add %p, 1
+\t%i =l sub %a, $tape
+\t%b =w cultl %i, 30000
+\tjnz %b, @in_bounds1, @off_tape
+@in_bounds1
+\t%v =w loadub %a
+\t%x =w mul %m, 3
+\t%v =w add %v, %x
+\tstoreb %v, %a
+\t%a =l add %p, 2
+\t%i =l sub %a, $tape
+\t%b =w cultl %i, 30000
+\tjnz %b, @in_bounds2, @off_tape
+@in_bounds2
+\t%v =w loadub %a
+\t%x =w mul %m, -1
+\t%v =w add %v, %x
+\tstoreb %v, %a
+@mul_done1
+\tstoreb 0, %p
+\t%p =l add %p, 1
+\t%i =l sub %p, $tape
+\t%b =w cultl %i, 30000
+\tjnz %b, @in_bounds3, @off_tape
+@in_bounds3
+\t%v =w loadub %p
+\tcall $putchar(w %v)
+@loop_head2
+\t%v =w loadub %p
+\tjnz %v, @loop_body2, @loop_end2
+@loop_body2
+\t%p =l add %p, -1
+\t%i =l sub %p, $tape
+\t%b =w cultl %i, 30000
+\tjnz %b, @in_bounds4, @off_tape
+@in_bounds4
+\tjmp @loop_head2
+@loop_end2
+\t%c =w call $getchar()
+\t%e =w ceqw %c, -1
+\tjnz %e, @read_done5, @read_store5
+@read_store5
+\tstoreb %c, %p
+@read_done5
+\tret 0
+@off_tape
+\tcall $write(w 2, l $off_tape_message, l 27)
+\tret 5
+}
+"
+        ));
+    }
+
+    #[test]
+    fn eof_behavior() {
+        let config = Config {
+            eof: EofBehavior::MinusOne,
+            ..Config::default()
+        };
+        let qbe = emit(",", &config);
+        assert!(qbe.contains(
+            "\tjnz %e, @read_eof1, @read_store1
+@read_eof1
+\t%c =w copy -1
+@read_store1
+\tstoreb %c, %p
+\tret 0
+"
+        ));
+    }
+
+    #[test]
+    fn wide_cells_and_wrapping_tape() {
+        let config = Config {
+            cell_width: CellWidth::Bits64,
+            tape: TapeConfig {
+                length: 100,
+                boundary: Boundary::Wrap,
+            },
+            ..Config::default()
+        };
+        let qbe = emit("[-<],", &config);
+        assert!(qbe.contains("data $tape = align 8 { z 800 }\n"));
+        assert!(qbe.contains("\t%v =l loadl %p\n\t%t =w cnel %v, 0\n\tjnz %t, @loop_body1"));
+        assert!(qbe.contains(
+            "\t%p =l sub %p, $tape\n\t%p =l add %p, 792\n\t%p =l urem %p, 800\n\t%p =l add %p, $tape\n"
+        ));
+        assert!(qbe.contains("\t%x =l extsw %c\n\tstorel %x, %p\n"));
+        assert!(!qbe.contains("off_tape"));
+    }
+
+    #[test]
+    fn only_wrapping_cells() {
+        let program = Module::parse(&['+']).unwrap();
+        let config = Config {
+            overflow: interpreter::config::Overflow::Error,
+            ..Config::default()
+        };
+        assert!(emit_qbe(&program, "t.bf", &config).is_err());
+    }
+
+    fn build(source: &str, config: &Config, executable: &Path) {
+        let ssa = executable.with_extension("ssa");
+        let asm = executable.with_extension("s");
+        std::fs::write(&ssa, emit(source, config)).unwrap();
+        let status = Command::new("qbe")
+            .arg("-o")
+            .arg(&asm)
+            .arg(&ssa)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let status = Command::new("cc")
+            .arg("-o")
+            .arg(executable)
+            .arg(&asm)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn compiles_samples() {
+        if installed("qbe") && installed("cc") {
+            check_samples(build);
+        }
+    }
+
+    #[test]
+    fn stops_off_the_tape() {
+        if installed("qbe") && installed("cc") {
+            check_stops_off_the_tape(build);
+        }
+    }
+}
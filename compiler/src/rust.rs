@@ -0,0 +1,381 @@
+use std::fmt::Write;
+
+use interpreter::{
+    config::{Boundary, CellWidth, Config, EofBehavior, TapeConfig},
+    ir::{Module, Position},
+};
+
+use crate::lower::{check, lower, uses_input, Lower, OFF_TAPE_MESSAGE, OFF_TAPE_STATUS};
+
+struct Emitter {
+    eof: EofBehavior,
+    tape: TapeConfig,
+    width: CellWidth,
+    body: String,
+    indent: usize,
+    /// Whether any check calls `off_tape`.
+    off_tape: bool,
+}
+
+impl Emitter {
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.body.push_str("    ");
+        }
+        self.body.push_str(line);
+        self.body.push('\n');
+    }
+
+    /// The tape index `offset` cells from `p`, wrapping around the tape with
+    /// [`Boundary::Wrap`]. Otherwise an index left of the tape wraps around
+    /// to a large number, for [`Emitter::check_bounds`] to catch.
+    fn index(&self, offset: isize) -> String {
+        if offset == 0 {
+            return "p".to_string();
+        }
+        if self.tape.boundary == Boundary::Wrap {
+            return format!("(p + {}) % TAPE_SIZE", offset);
+        }
+        if offset < 0 {
+            format!("p.wrapping_sub({})", offset.unsigned_abs())
+        } else {
+            format!("p.wrapping_add({})", offset)
+        }
+    }
+
+    /// Returns the `off_tape` error from `run` unless `index` is on the tape.
+    fn check_bounds(&mut self, index: &str) {
+        self.indented(&format!("if {} >= TAPE_SIZE {{", index));
+        self.line("return Err(off_tape());");
+        self.close();
+        self.off_tape = true;
+    }
+
+    /// `cell.wrapping_add(amount)`, or `wrapping_sub` for negative amounts.
+    fn wrapping(&self, cell: &str, amount: i64, operand: impl Fn(u64) -> String) -> String {
+        let method = if amount < 0 {
+            "wrapping_sub"
+        } else {
+            "wrapping_add"
+        };
+        format!("{}.{}({})", cell, method, operand(amount.unsigned_abs()))
+    }
+
+    fn indented(&mut self, line: &str) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    fn close(&mut self) {
+        self.indent -= 1;
+        self.line("}");
+    }
+}
+
+impl Lower for Emitter {
+    fn add(&mut self, amount: i64) {
+        let line = format!(
+            "tape[p] = {};",
+            self.wrapping("tape[p]", amount, |amount| amount.to_string())
+        );
+        self.line(&line);
+    }
+
+    fn shift(&mut self, offset: isize) {
+        let line = format!("p = {};", self.index(offset));
+        self.line(&line);
+        if self.tape.boundary != Boundary::Wrap {
+            self.check_bounds("p");
+        }
+    }
+
+    fn output(&mut self) {
+        if self.width == CellWidth::Bits8 {
+            self.line("output.write_all(&[tape[p]])?;");
+        } else {
+            self.line("output.write_all(&[tape[p] as u8])?;");
+        }
+    }
+
+    fn input(&mut self) {
+        match self.eof {
+            EofBehavior::Unchanged => {
+                self.indented("if let Some(byte) = read(input)? {");
+                self.line("tape[p] = Cell::from(byte);");
+                self.close();
+            }
+            EofBehavior::Zero => self.line("tape[p] = read(input)?.map_or(0, Cell::from);"),
+            EofBehavior::MinusOne => {
+                self.line("tape[p] = read(input)?.map_or(Cell::MAX, Cell::from);")
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.line("tape[p] = 0;");
+    }
+
+    fn loop_start(&mut self, label: usize, position: Position) {
+        let comment = format!("// loop{} at {}:{}", label, position.line, position.column);
+        self.line(&comment);
+        self.indented("while tape[p] != 0 {");
+    }
+
+    fn loop_end(&mut self, _: usize) {
+        self.close();
+    }
+
+    fn scan(&mut self, _: usize, _: Position, stride: isize) {
+        self.indented("while tape[p] != 0 {");
+        self.shift(stride);
+        self.close();
+    }
+
+    fn mul_start(&mut self, _: usize) {
+        self.indented("if tape[p] != 0 {");
+    }
+
+    fn mul(&mut self, offset: isize, factor: i64) {
+        let index = self.index(offset);
+        let cell = if self.tape.boundary == Boundary::Wrap {
+            format!("tape[{}]", index)
+        } else {
+            self.line(&format!("let q = {};", index));
+            self.check_bounds("q");
+            "tape[q]".to_string()
+        };
+        let update = self.wrapping(&cell, factor, |factor| match factor {
+            1 => "tape[p]".to_string(),
+            factor => format!("tape[p].wrapping_mul({})", factor),
+        });
+        self.line(&format!("{} = {};", cell, update));
+    }
+
+    fn mul_end(&mut self, _: usize) {
+        self.close();
+    }
+}
+
+const READ: &str = "
+/// Reads one byte, or `None` at the end of `input`.
+fn read(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+";
+
+const MAIN: &str = "
+fn main() -> io::Result<()> {
+    let mut output = io::BufWriter::new(io::stdout().lock());
+    run(&mut io::stdin().lock(), &mut output)
+}
+";
+
+/// A `main` that reports a move off the tape like the other backends.
+fn checked_main() -> String {
+    format!(
+        "
+fn main() -> io::Result<()> {{
+    let mut output = io::BufWriter::new(io::stdout().lock());
+    match run(&mut io::stdin().lock(), &mut output) {{
+        Err(err) if err.to_string() == OFF_TAPE => {{
+            output.flush()?;
+            eprintln!(\"{{}}\", OFF_TAPE);
+            std::process::exit({});
+        }}
+        result => result,
+    }}
+}}
+",
+        OFF_TAPE_STATUS
+    )
+}
+
+const OFF_TAPE_ERROR: &str = "
+/// The error `run` returns when the pointer moves off the tape.
+fn off_tape() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, OFF_TAPE)
+}
+";
+
+/// Translates a BF module into a self-contained Rust source file.
+///
+/// The file defines `pub fn run(input: &mut impl Read, output: &mut impl
+/// Write) -> io::Result<()>`, which can be copied into a library, and a
+/// `main` that runs it on stdin and stdout. Cells wrap with
+/// `wrapping_add`/`wrapping_sub`, and each loop is preceded by a comment with
+/// its source position. Unless the tape wraps, a move off the tape makes `run`
+/// return an error, which `main` reports like the other backends before
+/// exiting with status 5.
+pub fn emit_rust(program: &Module, module: &str, config: &Config) -> anyhow::Result<String> {
+    check(config)?;
+    let mut emitter = Emitter {
+        eof: config.eof,
+        tape: config.tape,
+        width: config.cell_width,
+        body: String::new(),
+        indent: 1,
+        off_tape: false,
+    };
+    let input = uses_input(&program.body);
+    emitter.line("let mut tape: Vec<Cell> = vec![0; TAPE_SIZE];");
+    emitter.line("let mut p: usize = 0;");
+    lower(&program.body, config, &mut emitter);
+    emitter.line("output.flush()");
+
+    let mut rust = String::new();
+    writeln!(rust, "//! Generated by bf from {}.", module)?;
+    writeln!(rust)?;
+    writeln!(rust, "use std::io::{{self, Read, Write}};")?;
+    writeln!(rust)?;
+    writeln!(rust, "const TAPE_SIZE: usize = {};", config.tape.length)?;
+    writeln!(rust)?;
+    writeln!(rust, "type Cell = u{};", config.cell_width.bits())?;
+    writeln!(rust)?;
+    if emitter.off_tape {
+        writeln!(
+            rust,
+            "const OFF_TAPE: &str = {:?};",
+            OFF_TAPE_MESSAGE.trim_end()
+        )?;
+        writeln!(rust)?;
+    }
+    writeln!(
+        rust,
+        "/// Runs the program, reading `,` from `input` and writing `.` to `output`."
+    )?;
+    writeln!(rust, "#[allow(unused_mut, unused_variables)]")?;
+    writeln!(
+        rust,
+        "pub fn run(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {{"
+    )?;
+    rust.push_str(&emitter.body);
+    writeln!(rust, "}}")?;
+    if input {
+        rust.push_str(READ);
+    }
+    if emitter.off_tape {
+        rust.push_str(OFF_TAPE_ERROR);
+        rust.push_str(&checked_main());
+    } else {
+        rust.push_str(MAIN);
+    }
+    Ok(rust)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command};
+
+    use super::*;
+    use crate::samples::{check_samples, check_stops_off_the_tape};
+    use interpreter::optimize::{optimize, Passes};
+
+    fn emit(source: &str, config: &Config) -> String {
+        let mut program = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut program, &Passes::level(2), config);
+        emit_rust(&program, "t.bf", config).unwrap()
+    }
+
+    #[test]
+    fn source_file() {
+        let rust = emit("+[->+++>-<<]>.\n[<],", &Config::default());
+        assert!(rust.starts_with("//! Generated by bf from t.bf.\n"));
+        assert!(rust.contains("const TAPE_SIZE: usize = 30000;\n"));
+        assert!(rust.contains("type Cell = u8;\n"));
+        assert!(rust.contains(
+            "    tape[p] = tape[p].wrapping_add(1);
+    if tape[p] != 0 {
+        let q = p.wrapping_add(1);
+        if q >= TAPE_SIZE {
+            return Err(off_tape());
+        }
+        tape[q] = tape[q].wrapping_add(tape[p].wrapping_mul(3));
+        let q = p.wrapping_add(2);
+        if q >= TAPE_SIZE {
+            return Err(off_tape());
+        }
+        tape[q] = tape[q].wrapping_sub(tape[p]);
+    }
+    tape[p] = 0;
+    p = p.wrapping_add(1);
+    if p >= TAPE_SIZE {
+        return Err(off_tape());
+    }
+    output.write_all(&[tape[p]])?;
+    while tape[p] != 0 {
+        p = p.wrapping_sub(1);
+        if p >= TAPE_SIZE {
+            return Err(off_tape());
+        }
+    }
+    if let Some(byte) = read(input)? {
+        tape[p] = Cell::from(byte);
+    }
+    output.flush()
+}
+"
+        ));
+        assert!(rust.contains("fn read(input: &mut impl Read)"));
+        assert!(rust.contains("const OFF_TAPE: &str = \"pointer moved off the tape\";\n"));
+        assert!(rust.contains("fn off_tape() -> io::Error {\n"));
+        assert!(rust.contains("            std::process::exit(5);\n"));
+        assert!(rust.ends_with("        result => result,\n    }\n}\n"));
+        let rust = emit("+.", &Config::default());
+        assert!(!rust.contains("fn read"));
+        assert!(!rust.contains("OFF_TAPE"));
+        assert!(rust.ends_with("run(&mut io::stdin().lock(), &mut output)\n}\n"));
+    }
+
+    #[test]
+    fn cell_type_and_tape() {
+        let config = Config {
+            cell_width: CellWidth::Bits16,
+            eof: EofBehavior::MinusOne,
+            tape: TapeConfig {
+                length: 100,
+                boundary: Boundary::Wrap,
+            },
+            ..Config::default()
+        };
+        let rust = emit(",[>,[.-]<-]<.", &config);
+        assert!(rust.contains("type Cell = u16;\n"));
+        assert!(rust.contains("    // loop1 at 1:2\n    while tape[p] != 0 {\n"));
+        assert!(rust.contains("        // loop2 at 1:5\n        while tape[p] != 0 {\n"));
+        assert!(rust.contains("p = (p + 99) % TAPE_SIZE;\n"));
+        assert!(rust.contains("output.write_all(&[tape[p] as u8])?;\n"));
+        assert!(rust.contains("tape[p] = read(input)?.map_or(Cell::MAX, Cell::from);\n"));
+        assert!(!rust.contains("OFF_TAPE"));
+    }
+
+    /// Compiles `source` with `rustc` into `executable`.
+    fn build(source: &str, config: &Config, executable: &Path) {
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let rust = executable.with_extension("rs");
+        std::fs::write(&rust, emit(source, config)).unwrap();
+        let status = Command::new(rustc)
+            .args(["--edition=2021", "-D", "warnings", "-o"])
+            .arg(executable)
+            .arg(&rust)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn compiles_samples() {
+        check_samples(build);
+    }
+
+    #[test]
+    fn stops_off_the_tape() {
+        check_stops_off_the_tape(build);
+    }
+}
@@ -0,0 +1,114 @@
+//! The sample programs the backend tests build and run, and the helpers that
+//! build, run and clean up after them.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use interpreter::config::{Boundary, Config, TapeConfig};
+
+use crate::lower::{OFF_TAPE_MESSAGE, OFF_TAPE_STATUS};
+
+/// A sample program as `(name, source, input, output)`.
+pub(crate) type Sample = (&'static str, &'static str, &'static [u8], &'static [u8]);
+
+pub(crate) const SAMPLES: [Sample; 6] = include!("../../samples/samples.rs");
+
+/// Programs that move left, move right, scan right and multiply left and
+/// right off the tape of [`off_tape_config`]. Cell 10 is close enough that
+/// only a bounds check stops a move there.
+pub(crate) const OFF_TAPE: [&str; 5] = [
+    "<+",
+    ">>>>>>>>>>+",
+    "+[>+]",
+    "+[>+<-]>[<<+>>-]",
+    "+[>>>>>>>>>>+<<<<<<<<<<-]",
+];
+
+/// A 10-cell tape with moves off it reported as errors.
+pub(crate) fn off_tape_config() -> Config {
+    Config {
+        tape: TapeConfig {
+            length: 10,
+            boundary: Boundary::Error,
+        },
+        ..Config::default()
+    }
+}
+
+/// A new directory under the system's temporary directory, removed with
+/// everything in it when dropped, even if the test fails.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "bf-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Whether `program` is on the `PATH`, for skipping tests that need it.
+pub(crate) fn installed(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok()
+}
+
+/// Builds `source` for `config` with `build`, which writes an executable to
+/// the path it is given, and runs it on `input`. `build` may put its
+/// intermediate files beside the executable; they are removed with it.
+pub(crate) fn run_sample(
+    source: &str,
+    config: &Config,
+    input: &[u8],
+    build: impl Fn(&str, &Config, &Path),
+) -> Output {
+    let dir = TempDir::new();
+    let executable = dir.path().join("program");
+    build(source, config, &executable);
+    let mut child = Command::new(&executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Checks that `build` gives executables writing the output of every sample
+/// with the default configuration.
+pub(crate) fn check_samples(build: impl Fn(&str, &Config, &Path)) {
+    for (name, source, input, expected) in SAMPLES {
+        let output = run_sample(source, &Config::default(), input, &build);
+        assert!(output.status.success(), "{}", name);
+        assert_eq!(output.stdout, expected, "{}", name);
+    }
+}
+
+/// Checks that `build` gives executables stopping every [`OFF_TAPE`] program
+/// with [`OFF_TAPE_MESSAGE`] and [`OFF_TAPE_STATUS`].
+pub(crate) fn check_stops_off_the_tape(build: impl Fn(&str, &Config, &Path)) {
+    for source in OFF_TAPE {
+        let output = run_sample(source, &off_tape_config(), b"", &build);
+        assert_eq!(output.status.code(), Some(OFF_TAPE_STATUS), "{}", source);
+        assert_eq!(output.stderr, OFF_TAPE_MESSAGE.as_bytes(), "{}", source);
+    }
+}
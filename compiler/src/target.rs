@@ -0,0 +1,230 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+
+/// An instruction set programs can be compiled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    X86,
+    Aarch64,
+    Riscv64,
+    Wasm32,
+}
+
+/// The format of object files and executables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFormat {
+    Elf,
+    MachO,
+    Coff,
+    Wasm,
+}
+
+/// How a program asks the kernel for I/O without going through libc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallAbi {
+    /// `syscall`, with the number in `rax` and arguments in `rdi`, `rsi` and
+    /// `rdx`.
+    LinuxX86_64,
+    /// `int $0x80`, with the number in `eax` and arguments in `ebx`, `ecx`
+    /// and `edx`.
+    LinuxX86,
+    /// `svc #0`, with the number in `x8` and arguments in `x0` to `x2`.
+    LinuxAarch64,
+    /// `ecall`, with the number in `a7` and arguments in `a0` to `a2`.
+    LinuxRiscv64,
+}
+
+/// The machine compiled programs run on, described by an LLVM target triple
+/// such as `x86_64-unknown-linux-gnu`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub triple: String,
+    pub arch: Arch,
+    /// The size of a pointer in bits.
+    pub pointer_width: u32,
+    /// The LLVM data layout string.
+    pub data_layout: &'static str,
+    /// The system call interface, on Linux. Other systems either have no
+    /// stable one or no kernel at all.
+    pub syscalls: Option<SyscallAbi>,
+    pub format: ObjectFormat,
+}
+
+impl Target {
+    /// The machine `bf` itself was built for.
+    pub fn host() -> anyhow::Result<Target> {
+        use std::env::consts::{ARCH, OS};
+
+        let arch = match ARCH {
+            "x86" => "i686",
+            arch => arch,
+        };
+        let triple = match OS {
+            "linux" => format!("{}-unknown-linux-gnu", arch),
+            "macos" => format!("{}-apple-darwin", arch),
+            "windows" => format!("{}-pc-windows-msvc", arch),
+            os => format!("{}-unknown-{}", arch, os),
+        };
+        triple.parse()
+    }
+
+    /// Rejects targets other than x86-64 Linux, for backends that write its
+    /// machine code and system calls themselves.
+    pub fn require_x86_64_linux(&self, backend: &str) -> anyhow::Result<()> {
+        if self.arch != Arch::X86_64
+            || self.format != ObjectFormat::Elf
+            || self.syscalls != Some(SyscallAbi::LinuxX86_64)
+        {
+            bail!(
+                "{} only supports x86_64 Linux, not {}; use the LLVM backend for other targets",
+                backend,
+                self.triple
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.triple)
+    }
+}
+
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('-');
+        let arch = match parts.next().unwrap_or_default() {
+            "x86_64" | "amd64" => Arch::X86_64,
+            "i386" | "i486" | "i586" | "i686" => Arch::X86,
+            "aarch64" | "arm64" => Arch::Aarch64,
+            "riscv64" => Arch::Riscv64,
+            "wasm32" => Arch::Wasm32,
+            arch => bail!(
+                "unsupported target '{}': unknown architecture '{}', expected \
+                 x86_64, i686, aarch64, riscv64 or wasm32",
+                s,
+                arch
+            ),
+        };
+        let rest: Vec<&str> = parts.collect();
+        let has = |names: &[&str]| {
+            rest.iter()
+                .any(|part| names.iter().any(|name| part.starts_with(name)))
+        };
+        let format = if arch == Arch::Wasm32 {
+            ObjectFormat::Wasm
+        } else if has(&["darwin", "macos", "ios"]) {
+            ObjectFormat::MachO
+        } else if has(&["windows", "win32"]) {
+            ObjectFormat::Coff
+        } else {
+            ObjectFormat::Elf
+        };
+        let syscalls = match (arch, has(&["linux"])) {
+            (Arch::X86_64, true) => Some(SyscallAbi::LinuxX86_64),
+            (Arch::X86, true) => Some(SyscallAbi::LinuxX86),
+            (Arch::Aarch64, true) => Some(SyscallAbi::LinuxAarch64),
+            (Arch::Riscv64, true) => Some(SyscallAbi::LinuxRiscv64),
+            _ => None,
+        };
+        let data_layout = match (arch, format) {
+            (Arch::X86_64, ObjectFormat::Elf) => {
+                "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+            }
+            (Arch::X86_64, ObjectFormat::MachO) => {
+                "e-m:o-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+            }
+            (Arch::X86_64, ObjectFormat::Coff) => {
+                "e-m:w-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+            }
+            (Arch::X86, ObjectFormat::Elf) => {
+                "e-m:e-p:32:32-p270:32:32-p271:32:32-p272:64:64-i128:128-f64:32:64-f80:32-n8:16:32-S128"
+            }
+            (Arch::X86, ObjectFormat::Coff) => {
+                "e-m:x-p:32:32-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32-a:0:32-S32"
+            }
+            (Arch::Aarch64, ObjectFormat::Elf) => {
+                "e-m:e-p270:32:32-p271:32:32-p272:64:64-i8:8:32-i16:16:32-i64:64-i128:128-n32:64-S128-Fn32"
+            }
+            (Arch::Aarch64, ObjectFormat::MachO) => {
+                "e-m:o-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-n32:64-S128-Fn32"
+            }
+            (Arch::Aarch64, ObjectFormat::Coff) => {
+                "e-m:w-p270:32:32-p271:32:32-p272:64:64-p:64:64-i32:32-i64:64-i128:128-n32:64-S128-Fn32"
+            }
+            (Arch::Riscv64, ObjectFormat::Elf) => "e-m:e-p:64:64-i64:64-i128:128-n32:64-S128",
+            (Arch::Wasm32, _) => {
+                "e-m:e-p:32:32-p10:8:8-p20:8:8-i64:64-i128:128-n32:64-S128-ni:1:10:20"
+            }
+            (arch, format) => bail!(
+                "unsupported target '{}': no {:?} {:?} data layout",
+                s,
+                arch,
+                format
+            ),
+        };
+        let pointer_width = match arch {
+            Arch::X86_64 | Arch::Aarch64 | Arch::Riscv64 => 64,
+            Arch::X86 | Arch::Wasm32 => 32,
+        };
+        Ok(Target {
+            triple: s.to_string(),
+            arch,
+            pointer_width,
+            data_layout,
+            syscalls,
+            format,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_triples() {
+        let target: Target = "x86_64-unknown-linux-gnu".parse().unwrap();
+        assert_eq!(target.arch, Arch::X86_64);
+        assert_eq!(target.pointer_width, 64);
+        assert_eq!(target.format, ObjectFormat::Elf);
+        assert_eq!(target.syscalls, Some(SyscallAbi::LinuxX86_64));
+        assert!(target.require_x86_64_linux("the ELF backend").is_ok());
+
+        let target: Target = "arm64-apple-macosx".parse().unwrap();
+        assert_eq!(target.arch, Arch::Aarch64);
+        assert_eq!(target.format, ObjectFormat::MachO);
+        assert_eq!(target.syscalls, None);
+        assert!(target.data_layout.starts_with("e-m:o-"));
+
+        let target: Target = "wasm32-unknown-unknown".parse().unwrap();
+        assert_eq!(target.pointer_width, 32);
+        assert_eq!(target.format, ObjectFormat::Wasm);
+
+        let target: Target = "i686-pc-windows-msvc".parse().unwrap();
+        assert_eq!(target.format, ObjectFormat::Coff);
+        assert!(target.data_layout.starts_with("e-m:x-p:32:32"));
+        assert_eq!(target.to_string(), "i686-pc-windows-msvc");
+    }
+
+    #[test]
+    fn rejects_unsupported_targets() {
+        assert!("mips-unknown-linux-gnu".parse::<Target>().is_err());
+        assert!("i686-apple-darwin".parse::<Target>().is_err());
+        let err = "aarch64-unknown-linux-gnu"
+            .parse::<Target>()
+            .unwrap()
+            .require_x86_64_linux("the ELF backend")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the ELF backend only supports x86_64 Linux, not aarch64-unknown-linux-gnu; \
+             use the LLVM backend for other targets"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context};
+
+use crate::target::Target;
+
+/// A locally installed LLVM toolchain able to turn textual IR into an
+/// executable.
+pub enum Toolchain {
+    /// `clang` compiles and links the IR in one step.
+    Clang { major: u32 },
+    /// `llc` produces an object file which the system `cc` links.
+    Llc { major: u32 },
+}
+
+impl Toolchain {
+    /// Looks for `clang`, then `llc`, on the `PATH`.
+    pub fn detect() -> Option<Toolchain> {
+        if let Some(major) = version("clang") {
+            return Some(Toolchain::Clang { major });
+        }
+        version("llc").map(|major| Toolchain::Llc { major })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Toolchain::Clang { .. } => "clang",
+            Toolchain::Llc { .. } => "llc",
+        }
+    }
+
+    /// The LLVM major version, which decides the IR it reads.
+    pub fn major(&self) -> u32 {
+        match self {
+            Toolchain::Clang { major } | Toolchain::Llc { major } => *major,
+        }
+    }
+
+    /// Builds `output` from the IR module stored at `ir`, cross-compiling
+    /// for `target` if one is given.
+    pub fn build(&self, ir: &Path, output: &Path, target: Option<&Target>) -> anyhow::Result<()> {
+        match self {
+            Toolchain::Clang { .. } => {
+                let mut command = Command::new("clang");
+                if let Some(target) = target {
+                    command.arg(format!("--target={}", target));
+                }
+                command.arg("-x").arg("ir").arg(ir).arg("-o").arg(output);
+                run(command)
+            }
+            Toolchain::Llc { .. } => {
+                let object = with_suffix(output, "o");
+                let mut command = Command::new("llc");
+                if let Some(target) = target {
+                    command.arg(format!("-mtriple={}", target));
+                }
+                command
+                    .arg("-relocation-model=pic")
+                    .arg("-filetype=obj")
+                    .arg(ir)
+                    .arg("-o")
+                    .arg(&object);
+                run(command)?;
+
+                let mut command = Command::new("cc");
+                command.arg(&object).arg("-o").arg(output);
+                run(command)?;
+                std::fs::remove_file(&object).context("remove object file")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A locally installed LLVM optimizer able to run the standard `-O`
+/// pipelines over textual IR.
+pub enum Optimizer {
+    /// `opt` runs the pipeline directly.
+    Opt { major: u32 },
+    /// `clang` runs it when compiling IR back to IR.
+    Clang { major: u32 },
+}
+
+impl Optimizer {
+    /// Looks for `opt`, then `clang`, on the `PATH`.
+    pub fn detect() -> Option<Optimizer> {
+        if let Some(major) = version("opt") {
+            return Some(Optimizer::Opt { major });
+        }
+        version("clang").map(|major| Optimizer::Clang { major })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Optimizer::Opt { .. } => "opt",
+            Optimizer::Clang { .. } => "clang",
+        }
+    }
+
+    /// The LLVM major version, which decides the IR it reads.
+    pub fn major(&self) -> u32 {
+        match self {
+            Optimizer::Opt { major } | Optimizer::Clang { major } => *major,
+        }
+    }
+
+    /// Optimizes the IR module stored at `ir` at `level` (0-3) into
+    /// `output`, returning the names of the passes that ran in the order
+    /// they first ran.
+    pub fn optimize(&self, ir: &Path, output: &Path, level: u32) -> anyhow::Result<Vec<String>> {
+        let mut command;
+        match self {
+            Optimizer::Opt { .. } => {
+                command = Command::new("opt");
+                command
+                    .arg(format!("-passes=default<O{}>", level))
+                    .arg("-debug-pass-manager")
+                    .arg("-S");
+            }
+            Optimizer::Clang { .. } => {
+                command = Command::new("clang");
+                command
+                    .arg(format!("-O{}", level))
+                    .args(["-Xclang", "-fdebug-pass-manager"])
+                    .args(["-S", "-emit-llvm", "-x", "ir"]);
+            }
+        }
+        command.arg(ir).arg("-o").arg(output);
+        let log = run_logged(command)?;
+        Ok(passes(&log))
+    }
+}
+
+/// The distinct passes named by `Running pass:` lines in a pass manager
+/// log, such as `Running pass: SROAPass on main`.
+fn passes(log: &str) -> Vec<String> {
+    let mut passes: Vec<String> = Vec::new();
+    for line in log.lines() {
+        let Some(rest) = line.trim_start().strip_prefix("Running pass: ") else {
+            continue;
+        };
+        let name = rest.split(" on ").next().unwrap_or(rest).trim();
+        if !passes.iter().any(|pass| pass == name) {
+            passes.push(name.to_string());
+        }
+    }
+    passes
+}
+
+/// Appends `.suffix` to `path` without replacing an existing extension.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn version(tool: &str) -> Option<u32> {
+    let output = Command::new(tool).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut words = text.split_whitespace();
+    words.find(|word| *word == "version")?;
+    let number = words.next()?;
+    let major: String = number.chars().take_while(|c| c.is_ascii_digit()).collect();
+    major.parse().ok()
+}
+
+/// Runs `command` and returns what it wrote to stderr. On failure the error
+/// includes that output, less the pass manager's own log lines.
+fn run_logged(mut command: Command) -> anyhow::Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => bail!("{} not found", program),
+        Err(err) => return Err(err).with_context(|| format!("run {}", program)),
+    };
+    let log = String::from_utf8_lossy(&output.stderr).into_owned();
+    if !output.status.success() {
+        let messages: Vec<&str> = log
+            .lines()
+            .filter(|line| {
+                !["Running ", "Invalidating ", "Clearing ", "Skipping "]
+                    .iter()
+                    .any(|prefix| line.trim_start().starts_with(prefix))
+            })
+            .collect();
+        bail!(
+            "{} failed with {}:\n{}",
+            program,
+            output.status,
+            messages.join("\n")
+        );
+    }
+    Ok(log)
+}
+
+fn run(mut command: Command) -> anyhow::Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = match command.status() {
+        Ok(status) => status,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => bail!("{} not found", program),
+        Err(err) => return Err(err).with_context(|| format!("run {}", program)),
+    };
+    if !status.success() {
+        bail!("{} failed with {}", program, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pass_names() {
+        let log = "Running pass: VerifierPass on [module]
+Running analysis: VerifierAnalysis on [module]
+Running pass: SROAPass on main
+Invalidating analysis: DominatorTreeAnalysis on main
+Running pass: EarlyCSEPass on main
+Running pass: SROAPass on main
+";
+        assert_eq!(passes(log), ["VerifierPass", "SROAPass", "EarlyCSEPass"]);
+    }
+}
@@ -0,0 +1,696 @@
+use std::fmt::Write;
+
+use anyhow::bail;
+use interpreter::{
+    config::{Boundary, CellWidth, Config, EofBehavior, TapeConfig},
+    ir::{Module, Node, Op},
+};
+
+use crate::{
+    leb128::{signed, unsigned},
+    lower::check,
+};
+
+const PAGE_SIZE: u64 = 65536;
+
+/// A WebAssembly module in both encodings.
+pub struct Wasm {
+    /// The text format, for reading.
+    pub text: String,
+    /// The binary format, for running.
+    pub binary: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Local {
+    /// The byte address of the current cell.
+    Pointer,
+    /// The last result of `getchar`.
+    Char,
+    /// The current cell while multiplying.
+    Value,
+}
+
+impl Local {
+    fn index(&self) -> u32 {
+        match self {
+            Local::Pointer => 0,
+            Local::Char => 1,
+            Local::Value => 2,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Local::Pointer => "$p",
+            Local::Char => "$c",
+            Local::Value => "$v",
+        }
+    }
+}
+
+/// The subset of WebAssembly instructions the emitter uses. Values are
+/// `i32`, except that 64-bit cells use the `I64` variants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Instr {
+    /// A comment in the text format only.
+    Comment(String),
+    Block,
+    Loop,
+    If,
+    End,
+    Br(u32),
+    BrIf(u32),
+    /// Calls an imported function: 0 is `putchar`, 1 is `getchar`.
+    Call(u32),
+    Drop,
+    Select,
+    Unreachable,
+    LocalGet(Local),
+    LocalSet(Local),
+    /// Loads a cell of the given width.
+    Load(CellWidth),
+    /// Stores a cell of the given width.
+    Store(CellWidth),
+    I32Const(i32),
+    I64Const(i64),
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32GeU,
+    I32Add,
+    I32And,
+    I32RemU,
+    I64Eqz,
+    I64Add,
+    I64Mul,
+    I32Mul,
+    I32WrapI64,
+    I64ExtendI32U,
+}
+
+impl Instr {
+    fn text(&self) -> String {
+        match self {
+            Instr::Comment(comment) => format!(";; {}", comment),
+            Instr::Block => "block".to_string(),
+            Instr::Loop => "loop".to_string(),
+            Instr::If => "if".to_string(),
+            Instr::End => "end".to_string(),
+            Instr::Br(depth) => format!("br {}", depth),
+            Instr::BrIf(depth) => format!("br_if {}", depth),
+            Instr::Call(0) => "call $putchar".to_string(),
+            Instr::Call(_) => "call $getchar".to_string(),
+            Instr::Drop => "drop".to_string(),
+            Instr::Select => "select".to_string(),
+            Instr::Unreachable => "unreachable".to_string(),
+            Instr::LocalGet(local) => format!("local.get {}", local.name()),
+            Instr::LocalSet(local) => format!("local.set {}", local.name()),
+            Instr::Load(width) => match width {
+                CellWidth::Bits8 => "i32.load8_u",
+                CellWidth::Bits16 => "i32.load16_u",
+                CellWidth::Bits32 => "i32.load",
+                CellWidth::Bits64 => "i64.load",
+            }
+            .to_string(),
+            Instr::Store(width) => match width {
+                CellWidth::Bits8 => "i32.store8",
+                CellWidth::Bits16 => "i32.store16",
+                CellWidth::Bits32 => "i32.store",
+                CellWidth::Bits64 => "i64.store",
+            }
+            .to_string(),
+            Instr::I32Const(value) => format!("i32.const {}", value),
+            Instr::I64Const(value) => format!("i64.const {}", value),
+            Instr::I32Eqz => "i32.eqz".to_string(),
+            Instr::I32Eq => "i32.eq".to_string(),
+            Instr::I32Ne => "i32.ne".to_string(),
+            Instr::I32GeU => "i32.ge_u".to_string(),
+            Instr::I32Add => "i32.add".to_string(),
+            Instr::I32And => "i32.and".to_string(),
+            Instr::I32RemU => "i32.rem_u".to_string(),
+            Instr::I64Eqz => "i64.eqz".to_string(),
+            Instr::I64Add => "i64.add".to_string(),
+            Instr::I64Mul => "i64.mul".to_string(),
+            Instr::I32Mul => "i32.mul".to_string(),
+            Instr::I32WrapI64 => "i32.wrap_i64".to_string(),
+            Instr::I64ExtendI32U => "i64.extend_i32_u".to_string(),
+        }
+    }
+
+    fn encode(&self, code: &mut Vec<u8>) {
+        // Blocks have no parameters or results.
+        const EMPTY: u8 = 0x40;
+        match self {
+            Instr::Comment(_) => (),
+            Instr::Block => code.extend_from_slice(&[0x02, EMPTY]),
+            Instr::Loop => code.extend_from_slice(&[0x03, EMPTY]),
+            Instr::If => code.extend_from_slice(&[0x04, EMPTY]),
+            Instr::End => code.push(0x0b),
+            Instr::Br(depth) => {
+                code.push(0x0c);
+                unsigned(code, *depth as u64);
+            }
+            Instr::BrIf(depth) => {
+                code.push(0x0d);
+                unsigned(code, *depth as u64);
+            }
+            Instr::Call(function) => {
+                code.push(0x10);
+                unsigned(code, *function as u64);
+            }
+            Instr::Drop => code.push(0x1a),
+            Instr::Select => code.push(0x1b),
+            Instr::Unreachable => code.push(0x00),
+            Instr::LocalGet(local) => {
+                code.push(0x20);
+                unsigned(code, local.index() as u64);
+            }
+            Instr::LocalSet(local) => {
+                code.push(0x21);
+                unsigned(code, local.index() as u64);
+            }
+            Instr::Load(width) => {
+                let (opcode, align) = match width {
+                    CellWidth::Bits8 => (0x2d, 0),
+                    CellWidth::Bits16 => (0x2f, 1),
+                    CellWidth::Bits32 => (0x28, 2),
+                    CellWidth::Bits64 => (0x29, 3),
+                };
+                // The alignment hint and a zero offset.
+                code.extend_from_slice(&[opcode, align, 0]);
+            }
+            Instr::Store(width) => {
+                let (opcode, align) = match width {
+                    CellWidth::Bits8 => (0x3a, 0),
+                    CellWidth::Bits16 => (0x3b, 1),
+                    CellWidth::Bits32 => (0x36, 2),
+                    CellWidth::Bits64 => (0x37, 3),
+                };
+                code.extend_from_slice(&[opcode, align, 0]);
+            }
+            Instr::I32Const(value) => {
+                code.push(0x41);
+                signed(code, *value as i64);
+            }
+            Instr::I64Const(value) => {
+                code.push(0x42);
+                signed(code, *value);
+            }
+            Instr::I32Eqz => code.push(0x45),
+            Instr::I32Eq => code.push(0x46),
+            Instr::I32Ne => code.push(0x47),
+            Instr::I32GeU => code.push(0x4f),
+            Instr::I32Add => code.push(0x6a),
+            Instr::I32And => code.push(0x71),
+            Instr::I32RemU => code.push(0x70),
+            Instr::I64Eqz => code.push(0x50),
+            Instr::I64Add => code.push(0x7c),
+            Instr::I64Mul => code.push(0x7e),
+            Instr::I32Mul => code.push(0x6c),
+            Instr::I32WrapI64 => code.push(0xa7),
+            Instr::I64ExtendI32U => code.push(0xad),
+        }
+    }
+}
+
+struct Emitter {
+    eof: EofBehavior,
+    tape: TapeConfig,
+    width: CellWidth,
+    /// The size of a cell in bytes.
+    size: i64,
+    code: Vec<Instr>,
+    next_loop: usize,
+}
+
+impl Emitter {
+    fn wide(&self) -> bool {
+        self.width == CellWidth::Bits64
+    }
+
+    /// Pushes a cell value.
+    fn constant(&mut self, value: i64) {
+        let value = self.width.wrap(value);
+        let instr = if self.wide() {
+            Instr::I64Const(value)
+        } else {
+            Instr::I32Const(value as i32)
+        };
+        self.code.push(instr);
+    }
+
+    /// Pushes the address `offset` cells from the pointer, wrapping around
+    /// the tape with [`Boundary::Wrap`].
+    fn address(&mut self, offset: isize) {
+        self.code.push(Instr::LocalGet(Local::Pointer));
+        if offset == 0 {
+            return;
+        }
+        let length = self.tape.length as i64;
+        let offset = match self.tape.boundary {
+            Boundary::Wrap => (offset as i64).rem_euclid(length),
+            _ => offset as i64,
+        };
+        self.code.push(Instr::I32Const((offset * self.size) as i32));
+        self.code.push(Instr::I32Add);
+        if self.tape.boundary == Boundary::Wrap {
+            self.code.push(Instr::I32Const((length * self.size) as i32));
+            self.code.push(Instr::I32RemU);
+        }
+    }
+
+    /// Traps unless the cell `offset` cells from the pointer is on the tape,
+    /// with [`Boundary::Error`]. Addresses left of the tape are large unsigned
+    /// numbers.
+    fn check_bounds(&mut self, offset: isize) {
+        if self.tape.boundary != Boundary::Error {
+            return;
+        }
+        self.address(offset);
+        let bytes = self.tape.length as i64 * self.size;
+        self.code.push(Instr::I32Const(bytes as i32));
+        self.code.push(Instr::I32GeU);
+        self.code.push(Instr::If);
+        self.code.push(Instr::Unreachable);
+        self.code.push(Instr::End);
+    }
+
+    fn load(&mut self, offset: isize) {
+        self.address(offset);
+        self.code.push(Instr::Load(self.width));
+    }
+
+    fn add(&mut self, amount: i64) {
+        self.address(0);
+        self.load(0);
+        self.constant(amount);
+        self.code.push(if self.wide() {
+            Instr::I64Add
+        } else {
+            Instr::I32Add
+        });
+        self.code.push(Instr::Store(self.width));
+    }
+
+    fn shift(&mut self, offset: isize) {
+        self.address(offset);
+        self.code.push(Instr::LocalSet(Local::Pointer));
+        self.check_bounds(0);
+    }
+
+    fn output(&mut self) {
+        self.load(0);
+        if self.wide() {
+            self.code.push(Instr::I32WrapI64);
+        }
+        if self.width != CellWidth::Bits8 {
+            self.code.push(Instr::I32Const(0xff));
+            self.code.push(Instr::I32And);
+        }
+        self.code.push(Instr::Call(0));
+        self.code.push(Instr::Drop);
+    }
+
+    /// Pushes the character read last, as a cell value.
+    fn char(&mut self) {
+        self.code.push(Instr::LocalGet(Local::Char));
+        if self.wide() {
+            self.code.push(Instr::I64ExtendI32U);
+        }
+    }
+
+    fn input(&mut self) {
+        self.code.push(Instr::Call(1));
+        self.code.push(Instr::LocalSet(Local::Char));
+        let on_eof = match self.eof {
+            EofBehavior::Unchanged => {
+                self.code.push(Instr::LocalGet(Local::Char));
+                self.code.push(Instr::I32Const(-1));
+                self.code.push(Instr::I32Ne);
+                self.code.push(Instr::If);
+                self.address(0);
+                self.char();
+                self.code.push(Instr::Store(self.width));
+                self.code.push(Instr::End);
+                return;
+            }
+            EofBehavior::Zero => 0,
+            EofBehavior::MinusOne => -1,
+        };
+        self.address(0);
+        self.constant(on_eof);
+        self.char();
+        self.code.push(Instr::LocalGet(Local::Char));
+        self.code.push(Instr::I32Const(-1));
+        self.code.push(Instr::I32Eq);
+        self.code.push(Instr::Select);
+        self.code.push(Instr::Store(self.width));
+    }
+
+    /// Opens a loop that exits once the current cell is zero.
+    fn loop_start(&mut self) {
+        self.code.push(Instr::Block);
+        self.code.push(Instr::Loop);
+        self.load(0);
+        self.code.push(if self.wide() {
+            Instr::I64Eqz
+        } else {
+            Instr::I32Eqz
+        });
+        self.code.push(Instr::BrIf(1));
+    }
+
+    fn loop_end(&mut self) {
+        self.code.push(Instr::Br(0));
+        self.code.push(Instr::End);
+        self.code.push(Instr::End);
+    }
+
+    fn mul(&mut self, offset: isize, factor: i64) {
+        self.load(0);
+        self.code.push(Instr::LocalSet(Local::Value));
+        self.code.push(Instr::LocalGet(Local::Value));
+        if self.wide() {
+            self.code.push(Instr::I64Eqz);
+            self.code.push(Instr::I32Eqz);
+        }
+        self.code.push(Instr::If);
+        self.check_bounds(offset);
+        self.address(offset);
+        self.load(offset);
+        self.code.push(Instr::LocalGet(Local::Value));
+        self.constant(factor);
+        if self.wide() {
+            self.code.push(Instr::I64Mul);
+            self.code.push(Instr::I64Add);
+        } else {
+            self.code.push(Instr::I32Mul);
+            self.code.push(Instr::I32Add);
+        }
+        self.code.push(Instr::Store(self.width));
+        self.code.push(Instr::End);
+    }
+
+    fn body(&mut self, body: &[Node]) {
+        for node in body {
+            match &node.op {
+                Op::Add(amount) => self.add(*amount),
+                Op::Move(offset) => self.shift(*offset),
+                Op::Output => self.output(),
+                Op::Input => self.input(),
+                Op::Loop(inner) => {
+                    self.next_loop += 1;
+                    self.code.push(Instr::Comment(format!(
+                        "loop{} at {}:{}",
+                        self.next_loop, node.position.line, node.position.column
+                    )));
+                    self.loop_start();
+                    self.body(inner);
+                    self.loop_end();
+                }
+                Op::Clear => {
+                    self.address(0);
+                    self.constant(0);
+                    self.code.push(Instr::Store(self.width));
+                }
+                Op::Scan(stride) => {
+                    self.loop_start();
+                    self.shift(*stride);
+                    self.loop_end();
+                }
+                Op::Mul { offset, factor } => self.mul(*offset, *factor),
+            }
+        }
+    }
+}
+
+/// Appends a section with the given id and contents.
+fn section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend_from_slice(contents);
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+const I32: u8 = 0x7f;
+const I64: u8 = 0x7e;
+
+/// Lowers a BF module to a WebAssembly module.
+///
+/// The tape is the exported linear memory `memory`, starting at address 0,
+/// and the program is the exported function `_start`. `.` and `,` call the
+/// imports `env.putchar(i32) -> i32`, which receives the low 8 bits of the
+/// cell, and `env.getchar() -> i32`, which returns -1 at the end of input.
+/// Unless the tape wraps, a move off the tape traps.
+pub fn emit_wasm(program: &Module, module: &str, config: &Config) -> anyhow::Result<Wasm> {
+    check(config)?;
+    let size = config.cell_width.bits() as u64 / 8;
+    let bytes = config.tape.length as u64 * size;
+    if bytes > i32::MAX as u64 {
+        bail!("WebAssembly tapes are limited to 2 GiB");
+    }
+    let pages = bytes.div_ceil(PAGE_SIZE).max(1);
+
+    let mut emitter = Emitter {
+        eof: config.eof,
+        tape: config.tape,
+        width: config.cell_width,
+        size: size as i64,
+        code: Vec::new(),
+        next_loop: 0,
+    };
+    emitter.body(&program.body);
+    let value = if emitter.wide() { I64 } else { I32 };
+
+    let mut text = String::new();
+    writeln!(text, ";; Generated by bf from {}.", module)?;
+    writeln!(text, "(module")?;
+    writeln!(
+        text,
+        "  (import \"env\" \"putchar\" (func $putchar (param i32) (result i32)))"
+    )?;
+    writeln!(
+        text,
+        "  (import \"env\" \"getchar\" (func $getchar (result i32)))"
+    )?;
+    writeln!(text, "  (memory (export \"memory\") {})", pages)?;
+    writeln!(text, "  (func (export \"_start\")")?;
+    writeln!(
+        text,
+        "    (local $p i32) (local $c i32) (local $v {})",
+        if value == I64 { "i64" } else { "i32" }
+    )?;
+    let mut depth = 2;
+    for instr in &emitter.code {
+        if *instr == Instr::End {
+            depth -= 1;
+        }
+        writeln!(text, "{}{}", "  ".repeat(depth), instr.text())?;
+        if matches!(instr, Instr::Block | Instr::Loop | Instr::If) {
+            depth += 1;
+        }
+    }
+    writeln!(text, "  )")?;
+    writeln!(text, ")")?;
+
+    let mut binary = b"\0asm".to_vec();
+    binary.extend_from_slice(&1u32.to_le_bytes());
+    // putchar: (i32) -> i32, getchar: () -> i32, _start: () -> ()
+    section(
+        &mut binary,
+        1,
+        &[3, 0x60, 1, I32, 1, I32, 0x60, 0, 1, I32, 0x60, 0, 0],
+    );
+    let mut imports = vec![2];
+    for (function, kind) in [("putchar", 0), ("getchar", 1)] {
+        name(&mut imports, "env");
+        name(&mut imports, function);
+        imports.extend_from_slice(&[0x00, kind]);
+    }
+    section(&mut binary, 2, &imports);
+    section(&mut binary, 3, &[1, 2]);
+    let mut memory = vec![1, 0];
+    unsigned(&mut memory, pages);
+    section(&mut binary, 5, &memory);
+    let mut exports = vec![2];
+    name(&mut exports, "_start");
+    exports.extend_from_slice(&[0x00, 2]);
+    name(&mut exports, "memory");
+    exports.extend_from_slice(&[0x02, 0]);
+    section(&mut binary, 7, &exports);
+
+    let mut body = vec![2, 2, I32, 1, value];
+    for instr in &emitter.code {
+        instr.encode(&mut body);
+    }
+    body.push(0x0b);
+    let mut code = vec![1];
+    unsigned(&mut code, body.len() as u64);
+    code.extend_from_slice(&body);
+    section(&mut binary, 10, &code);
+
+    Ok(Wasm { text, binary })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::{off_tape_config, OFF_TAPE, SAMPLES};
+    use interpreter::optimize::{optimize, Passes};
+    use wasmi::{Caller, Engine, Linker, Store};
+
+    struct Io {
+        input: Vec<u8>,
+        read: usize,
+        output: Vec<u8>,
+    }
+
+    /// Runs a binary module, feeding it `input`, and returns its output.
+    fn run(binary: &[u8], input: &[u8]) -> Vec<u8> {
+        call(binary, input).unwrap()
+    }
+
+    /// Runs a binary module like [`run`], returning any trap.
+    fn call(binary: &[u8], input: &[u8]) -> Result<Vec<u8>, wasmi::Error> {
+        let engine = Engine::default();
+        let module = wasmi::Module::new(&engine, binary).unwrap();
+        let io = Io {
+            input: input.to_vec(),
+            read: 0,
+            output: Vec::new(),
+        };
+        let mut store = Store::new(&engine, io);
+        let mut linker = Linker::<Io>::new(&engine);
+        linker
+            .func_wrap("env", "putchar", |mut caller: Caller<Io>, c: i32| {
+                caller.data_mut().output.push(c as u8);
+                c
+            })
+            .unwrap();
+        linker
+            .func_wrap("env", "getchar", |mut caller: Caller<Io>| {
+                let io = caller.data_mut();
+                match io.input.get(io.read) {
+                    Some(byte) => {
+                        io.read += 1;
+                        *byte as i32
+                    }
+                    None => -1,
+                }
+            })
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let start = instance.get_typed_func::<(), ()>(&store, "_start").unwrap();
+        start.call(&mut store, ())?;
+        Ok(store.into_data().output)
+    }
+
+    fn emit(source: &str, config: &Config) -> Wasm {
+        let mut program = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut program, &Passes::level(2), config);
+        emit_wasm(&program, "t.bf", config).unwrap()
+    }
+
+    #[test]
+    fn text_format() {
+        let wasm = emit("+[->++<]>.", &Config::default());
+        assert!(wasm
+            .text
+            .starts_with(";; Generated by bf from t.bf.\n(module\n"));
+        assert!(wasm.text.contains("  (memory (export \"memory\") 1)\n"));
+        assert!(wasm.text.contains(
+            "    local.get $p
+    i32.load8_u
+    local.set $v
+    local.get $v
+    if
+      local.get $p
+      i32.const 1
+      i32.add
+"
+        ));
+        assert!(wasm.text.contains("    call $putchar\n    drop\n"));
+        assert_eq!(&wasm.binary[..8], b"\0asm\x01\0\0\0");
+    }
+
+    #[test]
+    fn runs_samples() {
+        // Mandelbrot takes minutes in an unoptimized `wasmi`.
+        for (name, source, input, expected) in SAMPLES {
+            if name == "mandelbrot" {
+                continue;
+            }
+            let wasm = emit(source, &Config::default());
+            assert_eq!(run(&wasm.binary, input), expected, "{}", name);
+            // The text format describes the same program.
+            let parsed = wat::parse_str(&wasm.text).unwrap();
+            assert_eq!(run(&parsed, input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn cells_and_tape() {
+        for (cell_width, eof) in [
+            (CellWidth::Bits16, EofBehavior::Zero),
+            (CellWidth::Bits32, EofBehavior::Unchanged),
+            (CellWidth::Bits64, EofBehavior::MinusOne),
+        ] {
+            let config = Config {
+                cell_width,
+                eof,
+                tape: TapeConfig {
+                    length: 10,
+                    boundary: Boundary::Wrap,
+                },
+                ..Config::default()
+            };
+            // 256 does not fit in 8 bits; cell 9 is reached by wrapping.
+            let wasm = emit(
+                "++++++++[<++++++++[<++++>-]>-]<<[>>+<<-]>>-.+.,.,.",
+                &config,
+            );
+            let expected_eof = match eof {
+                EofBehavior::Unchanged => b'a',
+                EofBehavior::Zero => 0,
+                EofBehavior::MinusOne => 0xff,
+            };
+            assert_eq!(run(&wasm.binary, b"a"), [0xff, 0, b'a', expected_eof]);
+            assert_eq!(
+                run(&wat::parse_str(&wasm.text).unwrap(), b"a"),
+                [0xff, 0, b'a', expected_eof]
+            );
+        }
+    }
+
+    #[test]
+    fn traps_off_the_tape() {
+        let config = off_tape_config();
+        let wasm = emit(">", &config);
+        assert!(wasm.text.contains(
+            "    local.set $p
+    local.get $p
+    i32.const 10
+    i32.ge_u
+    if
+      unreachable
+    end
+"
+        ));
+        // Cell 10 is still in the memory's first page, so only the checks
+        // trap there.
+        for source in OFF_TAPE {
+            let wasm = emit(source, &config);
+            assert!(call(&wasm.binary, b"").is_err());
+            assert!(call(&wat::parse_str(&wasm.text).unwrap(), b"").is_err());
+        }
+    }
+}
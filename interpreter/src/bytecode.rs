@@ -0,0 +1,128 @@
+use std::fmt;
+
+use crate::ir::{Module, Node, Op, Position};
+
+/// One instruction of the bytecode VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instr {
+    Add(i64),
+    Move(isize),
+    Output,
+    Input,
+    /// Jump to the given instruction, just past the matching `Jnz`, if the
+    /// current cell is zero.
+    Jz(usize),
+    /// Jump to the given instruction, just past the matching `Jz`, unless
+    /// the current cell is zero.
+    Jnz(usize),
+    Clear,
+    Scan(isize),
+    Mul {
+        offset: isize,
+        factor: i64,
+    },
+}
+
+/// A module flattened into instructions with resolved jump targets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bytecode {
+    pub code: Vec<Instr>,
+    /// The source position of each instruction in `code`.
+    pub positions: Vec<Position>,
+}
+
+impl Bytecode {
+    pub fn compile(module: &Module) -> Bytecode {
+        let mut bytecode = Bytecode::default();
+        bytecode.body(&module.body);
+        bytecode
+    }
+
+    fn push(&mut self, instr: Instr, position: Position) {
+        self.code.push(instr);
+        self.positions.push(position);
+    }
+
+    fn body(&mut self, body: &[Node]) {
+        for node in body {
+            let instr = match &node.op {
+                Op::Add(amount) => Instr::Add(*amount),
+                Op::Move(offset) => Instr::Move(*offset),
+                Op::Output => Instr::Output,
+                Op::Input => Instr::Input,
+                Op::Loop(inner) => {
+                    let start = self.code.len();
+                    // Patched once the end of the loop is known.
+                    self.push(Instr::Jz(0), node.position);
+                    self.body(inner);
+                    self.push(Instr::Jnz(start + 1), node.position);
+                    self.code[start] = Instr::Jz(self.code.len());
+                    continue;
+                }
+                Op::Clear => Instr::Clear,
+                Op::Scan(stride) => Instr::Scan(*stride),
+                Op::Mul { offset, factor } => Instr::Mul {
+                    offset: *offset,
+                    factor: *factor,
+                },
+            };
+            self.push(instr, node.position);
+        }
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instr::Add(amount) => write!(f, "ADD {}", amount),
+            Instr::Move(offset) => write!(f, "MOVE {}", offset),
+            Instr::Output => write!(f, "OUT"),
+            Instr::Input => write!(f, "IN"),
+            Instr::Jz(target) => write!(f, "JZ {}", target),
+            Instr::Jnz(target) => write!(f, "JNZ {}", target),
+            Instr::Clear => write!(f, "CLEAR"),
+            Instr::Scan(stride) => write!(f, "SCAN {}", stride),
+            Instr::Mul { offset, factor } => write!(f, "MUL {}, {}", offset, factor),
+        }
+    }
+}
+
+/// Lists one instruction per line, prefixed with its index.
+impl fmt::Display for Bytecode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, instr) in self.code.iter().enumerate() {
+            writeln!(f, "{:4}  {}", index, instr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        optimize::{optimize, Passes},
+    };
+
+    #[test]
+    fn jumps() {
+        let mut module = Module::parse(&"+[>[-]<-]>.".chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut module, &Passes::level(2), &Config::default());
+        let bytecode = Bytecode::compile(&module);
+        assert_eq!(
+            bytecode.to_string(),
+            "   0  ADD 1
+   1  JZ 7
+   2  MOVE 1
+   3  CLEAR
+   4  MOVE -1
+   5  ADD -1
+   6  JNZ 2
+   7  MOVE 1
+   8  OUT
+"
+        );
+        assert_eq!(bytecode.positions[6], Position { line: 1, column: 2 });
+    }
+}
@@ -0,0 +1,218 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+
+/// How `.` output is decoded before it reaches the output stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMode {
+    /// Treat the bytes as UTF-8, replacing invalid sequences with U+FFFD.
+    Utf8,
+    /// Treat every byte as the Unicode code point of the same value.
+    Latin1,
+}
+
+impl FromStr for TextMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf8" | "utf-8" => Ok(TextMode::Utf8),
+            "latin1" | "latin-1" => Ok(TextMode::Latin1),
+            _ => bail!("unknown text mode '{}', expected utf8 or latin1", s),
+        }
+    }
+}
+
+/// What `,` stores once the input is exhausted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EofBehavior {
+    /// Leave the current cell as it is.
+    #[default]
+    Unchanged,
+    /// Store 0.
+    Zero,
+    /// Store -1, i.e. every bit of the cell set.
+    MinusOne,
+}
+
+impl FromStr for EofBehavior {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchanged" => Ok(EofBehavior::Unchanged),
+            "zero" => Ok(EofBehavior::Zero),
+            "minus-one" => Ok(EofBehavior::MinusOne),
+            _ => bail!(
+                "unknown EOF behavior '{}', expected unchanged, zero or minus-one",
+                s
+            ),
+        }
+    }
+}
+
+/// The number of bits in a tape cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CellWidth {
+    #[default]
+    Bits8,
+    Bits16,
+    Bits32,
+    Bits64,
+}
+
+impl CellWidth {
+    pub fn bits(&self) -> u32 {
+        match self {
+            CellWidth::Bits8 => 8,
+            CellWidth::Bits16 => 16,
+            CellWidth::Bits32 => 32,
+            CellWidth::Bits64 => 64,
+        }
+    }
+
+    /// Reduces `value` modulo 2^bits into the signed range of the width, so
+    /// that amounts that differ by a multiple of the modulus compare equal.
+    pub fn wrap(&self, value: i64) -> i64 {
+        let shift = 64 - self.bits();
+        (value << shift) >> shift
+    }
+}
+
+impl FromStr for CellWidth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(CellWidth::Bits8),
+            "16" => Ok(CellWidth::Bits16),
+            "32" => Ok(CellWidth::Bits32),
+            "64" => Ok(CellWidth::Bits64),
+            _ => bail!("unsupported cell width '{}', expected 8, 16, 32 or 64", s),
+        }
+    }
+}
+
+/// What `+` and `-` do when a cell would leave its range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wrap around modulo 2^bits.
+    #[default]
+    Wrap,
+    /// Stay at 0 or at the largest value.
+    Saturate,
+    /// Stop with [`RuntimeError::CellOverflow`](crate::error::RuntimeError).
+    Error,
+}
+
+impl FromStr for Overflow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(Overflow::Wrap),
+            "saturate" => Ok(Overflow::Saturate),
+            "error" => Ok(Overflow::Error),
+            _ => bail!(
+                "unknown overflow policy '{}', expected wrap, saturate or error",
+                s
+            ),
+        }
+    }
+}
+
+/// Which interpreter runs a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Compile the optimized IR to bytecode and run it, see
+    /// [`execute`](crate::execute).
+    #[default]
+    Vm,
+    /// Run the source command by command, see
+    /// [`interpret`](crate::interpret). Ignores optimization settings.
+    Legacy,
+    /// Compile the optimized IR to x86-64 machine code, see
+    /// [`execute_jit`](crate::execute_jit).
+    Jit,
+}
+
+impl FromStr for Engine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vm" => Ok(Engine::Vm),
+            "legacy" => Ok(Engine::Legacy),
+            "jit" => Ok(Engine::Jit),
+            _ => bail!("unknown engine '{}', expected vm, legacy or jit", s),
+        }
+    }
+}
+
+/// What happens when the data pointer moves off either end of the tape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Boundary {
+    /// Stop with a pointer underflow or overflow error.
+    #[default]
+    Error,
+    /// Continue from the other end of the tape.
+    Wrap,
+    /// Add cells on the side the pointer moved off.
+    Grow,
+    /// Never run off the tape: cells past `length` on either side are kept
+    /// in pages allocated when first written.
+    Unbounded,
+}
+
+impl FromStr for Boundary {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Boundary::Error),
+            "wrap" => Ok(Boundary::Wrap),
+            "grow" => Ok(Boundary::Grow),
+            "unbounded" => Ok(Boundary::Unbounded),
+            _ => bail!(
+                "unknown tape boundary '{}', expected error, wrap, grow or unbounded",
+                s
+            ),
+        }
+    }
+}
+
+/// The size of the tape and what happens at its ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapeConfig {
+    /// Number of cells, or the initial number with [`Boundary::Grow`] and
+    /// [`Boundary::Unbounded`].
+    pub length: usize,
+    pub boundary: Boundary,
+}
+
+impl Default for TapeConfig {
+    fn default() -> Self {
+        TapeConfig {
+            length: 30000,
+            boundary: Boundary::Error,
+        }
+    }
+}
+
+/// Settings shared by every execution engine and compiler backend.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Stop with [`RuntimeError::StepLimit`](crate::error::RuntimeError)
+    /// after this many steps.
+    pub step_limit: Option<u64>,
+    /// Decode output for display instead of writing each cell as one byte.
+    pub text_mode: Option<TextMode>,
+    /// What `,` stores at the end of input.
+    pub eof: EofBehavior,
+    /// Width of every tape cell.
+    pub cell_width: CellWidth,
+    /// What happens when a cell is incremented or decremented out of range.
+    pub overflow: Overflow,
+    /// Size and boundary policy of the tape.
+    pub tape: TapeConfig,
+}
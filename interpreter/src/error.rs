@@ -0,0 +1,185 @@
+use std::{fmt, io};
+
+use crate::ir::Position;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnmatchedBracket {
+    /// Either `[` or `]`.
+    pub bracket: char,
+    pub position: Position,
+}
+
+/// The source does not form a valid program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Every unmatched bracket, in source order.
+    pub unmatched: Vec<UnmatchedBracket>,
+}
+
+impl ParseError {
+    /// Renders each unmatched bracket with its source line and a caret
+    /// under the offending column.
+    pub fn snippet(&self, content: &[char]) -> String {
+        self.unmatched
+            .iter()
+            .map(|bracket| {
+                let message = format!(
+                    "unmatched '{}' at {}:{}",
+                    bracket.bracket, bracket.position.line, bracket.position.column
+                );
+                snippet(content, bracket.position, &message)
+            })
+            .collect()
+    }
+}
+
+/// Renders `message` followed by the source line at `position` and a caret
+/// under its column.
+pub fn snippet(content: &[char], position: Position, message: &str) -> String {
+    annotate(content, position, "error", message)
+}
+
+/// Like [`snippet`], with `label` in place of `error`, e.g.
+/// `warning[dead-loop]`.
+pub fn annotate(content: &[char], position: Position, label: &str, message: &str) -> String {
+    let Position { line, column } = position;
+    let text: String = content
+        .split(|c| *c == '\n')
+        .nth(line - 1)
+        .unwrap_or_default()
+        .iter()
+        .collect();
+    // Keep tabs so the caret lines up with the source.
+    let padding: String = text
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = line.to_string().len();
+    format!(
+        "{}: {}\n{} | {}\n{:width$} | {}^\n",
+        label,
+        message,
+        line,
+        text.trim_end_matches('\r'),
+        "",
+        padding,
+        width = width
+    )
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let brackets: Vec<String> = self
+            .unmatched
+            .iter()
+            .map(|bracket| {
+                format!(
+                    "'{}' at {}:{}",
+                    bracket.bracket, bracket.position.line, bracket.position.column
+                )
+            })
+            .collect();
+        write!(f, "unmatched {}", brackets.join(", "))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Why a running program was stopped.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// The program was rejected before it started.
+    Parse(ParseError),
+    /// The data pointer moved left of the first cell.
+    PointerUnderflow { position: Position },
+    /// The data pointer moved right of the last cell.
+    PointerOverflow { position: Position },
+    /// A cell left its range under [`Overflow::Error`](crate::config::Overflow).
+    CellOverflow {
+        position: Position,
+        /// Index of the cell, counted from the cell the program started on.
+        cell: isize,
+        /// The value of the cell before the failing instruction.
+        previous: u64,
+    },
+    /// Reading input or writing output failed.
+    Io {
+        position: Position,
+        source: io::Error,
+    },
+    /// The configured number of steps ran out.
+    StepLimit { position: Position, limit: u64 },
+}
+
+impl RuntimeError {
+    /// The position of the instruction that failed, if execution started.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            RuntimeError::Parse(_) => None,
+            RuntimeError::PointerUnderflow { position }
+            | RuntimeError::PointerOverflow { position }
+            | RuntimeError::CellOverflow { position, .. }
+            | RuntimeError::Io { position, .. }
+            | RuntimeError::StepLimit { position, .. } => Some(*position),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::Parse(err) => return write!(f, "{}", err),
+            RuntimeError::PointerUnderflow { .. } => write!(f, "pointer moved left of cell 0")?,
+            RuntimeError::PointerOverflow { .. } => write!(f, "pointer moved past the last cell")?,
+            RuntimeError::CellOverflow { cell, previous, .. } => {
+                write!(f, "cell {} overflowed from {}", cell, previous)?
+            }
+            RuntimeError::Io { source, .. } => write!(f, "I/O error: {}", source)?,
+            RuntimeError::StepLimit { limit, .. } => {
+                write!(f, "step limit of {} exhausted", limit)?
+            }
+        }
+        if let Some(position) = self.position() {
+            write!(f, " at {}:{}", position.line, position.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuntimeError::Parse(err) => Some(err),
+            RuntimeError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for RuntimeError {
+    fn from(err: ParseError) -> Self {
+        RuntimeError::Parse(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::Module;
+
+    #[test]
+    fn snippet() {
+        let content: Vec<char> = include_str!("../../samples/error_no_close_paren.bf")
+            .chars()
+            .collect();
+        let error = Module::parse(&content).unwrap_err();
+        assert_eq!(error.to_string(), "unmatched ']' at 1:7");
+        assert_eq!(
+            error.snippet(&content),
+            "error: unmatched ']' at 1:7
+1 | [++]->]
+  |       ^
+"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+
+use crate::error::{ParseError, UnmatchedBracket};
+
+/// Location of a command in the BF source, both 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// Add to the current cell, wrapping around the cell width.
+    Add(i64),
+    /// Move the data pointer by the given number of cells.
+    Move(isize),
+    Output,
+    Input,
+    /// Run the body while the current cell is not zero.
+    Loop(Vec<Node>),
+    /// Set the current cell to zero.
+    Clear,
+    /// Move by the given stride until the current cell is zero.
+    Scan(isize),
+    /// Add the current cell times `factor` to the cell at `offset`.
+    Mul {
+        offset: isize,
+        factor: i64,
+    },
+}
+
+/// An operation together with the position of the command it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub op: Op,
+    pub position: Position,
+}
+
+impl Node {
+    pub fn new(op: Op, position: Position) -> Node {
+        Node { op, position }
+    }
+}
+
+/// A parsed BF program.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Module {
+    pub body: Vec<Node>,
+}
+
+impl Module {
+    /// Parses BF source, ignoring every character that is not a command.
+    pub fn parse(content: &[char]) -> Result<Module, ParseError> {
+        check_brackets(content)?;
+
+        let mut blocks: Vec<(Vec<Node>, Position)> = Vec::new();
+        let mut body = Vec::new();
+        for (command, position) in commands(content) {
+            let op = match command {
+                '+' => Op::Add(1),
+                '-' => Op::Add(-1),
+                '>' => Op::Move(1),
+                '<' => Op::Move(-1),
+                '.' => Op::Output,
+                ',' => Op::Input,
+                '[' => {
+                    blocks.push((std::mem::take(&mut body), position));
+                    continue;
+                }
+                _ => {
+                    let (outer, start) = blocks.pop().expect("brackets are balanced");
+                    let inner = std::mem::replace(&mut body, outer);
+                    body.push(Node::new(Op::Loop(inner), start));
+                    continue;
+                }
+            };
+            body.push(Node::new(op, position));
+        }
+        Ok(Module { body })
+    }
+}
+
+/// Yields every BF command in `content` with its position.
+pub fn commands(content: &[char]) -> impl Iterator<Item = (char, Position)> + '_ {
+    let mut position = Position { line: 1, column: 1 };
+    content.iter().filter_map(move |&command| {
+        let current = position;
+        if command == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+        "+-<>.,[]".contains(command).then_some((command, current))
+    })
+}
+
+/// Reports every `[` without a matching `]` and vice versa.
+pub(crate) fn check_brackets(content: &[char]) -> Result<(), ParseError> {
+    let mut open = Vec::new();
+    let mut unmatched = Vec::new();
+    for (command, position) in commands(content) {
+        match command {
+            '[' => open.push(position),
+            ']' if open.pop().is_none() => unmatched.push(UnmatchedBracket {
+                bracket: ']',
+                position,
+            }),
+            _ => (),
+        }
+    }
+    unmatched.extend(open.into_iter().map(|position| UnmatchedBracket {
+        bracket: '[',
+        position,
+    }));
+
+    if unmatched.is_empty() {
+        Ok(())
+    } else {
+        unmatched.sort_by_key(|bracket| (bracket.position.line, bracket.position.column));
+        Err(ParseError { unmatched })
+    }
+}
+
+fn dump(f: &mut fmt::Formatter, body: &[Node], depth: usize) -> fmt::Result {
+    for node in body {
+        write!(f, "{:width$}", "", width = depth * 2)?;
+        match &node.op {
+            Op::Add(amount) => writeln!(f, "add {}", amount)?,
+            Op::Move(offset) => writeln!(f, "move {}", offset)?,
+            Op::Output => writeln!(f, "output")?,
+            Op::Input => writeln!(f, "input")?,
+            Op::Clear => writeln!(f, "clear")?,
+            Op::Scan(stride) => writeln!(f, "scan {}", stride)?,
+            Op::Mul { offset, factor } => writeln!(f, "mul {}, {}", offset, factor)?,
+            Op::Loop(inner) => {
+                writeln!(f, "loop {{")?;
+                dump(f, inner, depth + 1)?;
+                writeln!(f, "{:width$}}}", "", width = depth * 2)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The textual dump printed by `--dump-ir`: one operation per line, loop
+/// bodies indented by two spaces.
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        dump(f, &self.body, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Module, ParseError> {
+        Module::parse(&source.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn nested_loops() {
+        let module = parse("+[->[<]]x.\n,").unwrap();
+        assert_eq!(
+            module.to_string(),
+            "add 1
+loop {
+  add -1
+  move 1
+  loop {
+    move -1
+  }
+}
+output
+input
+"
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let module = parse("+\n ab[.]").unwrap();
+        assert_eq!(module.body[0].position, Position { line: 1, column: 1 });
+        assert_eq!(module.body[1].position, Position { line: 2, column: 4 });
+        match &module.body[1].op {
+            Op::Loop(inner) => {
+                assert_eq!(inner[0].position, Position { line: 2, column: 5 })
+            }
+            op => panic!("expected loop, got {:?}", op),
+        }
+    }
+
+    #[test]
+    fn unmatched() {
+        assert!(parse("[").is_err());
+        assert!(parse("]").is_err());
+        assert!(parse("[[]").is_err());
+    }
+
+    #[test]
+    fn every_unmatched_bracket_is_reported() {
+        let error = parse("[++]->]\n[ [").unwrap_err();
+        let found: Vec<_> = error
+            .unmatched
+            .iter()
+            .map(|bracket| {
+                (
+                    bracket.bracket,
+                    bracket.position.line,
+                    bracket.position.column,
+                )
+            })
+            .collect();
+        assert_eq!(found, [(']', 1, 7), ('[', 2, 1), ('[', 2, 3)]);
+    }
+}
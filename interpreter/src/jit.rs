@@ -0,0 +1,504 @@
+//! An x86-64 JIT for the optimized IR.
+//!
+//! The generated function keeps the tape start in `rbx`, the current cell
+//! in `r12`, the tape end in `r13` and the I/O context in `r14`. It calls
+//! back into Rust for `.` and `,`, and returns 0 on success or a [`Fault`]
+//! code with the index of the failing site.
+
+use std::{
+    ffi::c_void,
+    io::{Read, Write},
+    ptr,
+};
+
+use crate::{
+    config::{Boundary, CellWidth, Config, EofBehavior, Overflow},
+    error::RuntimeError,
+    ir::{Module, Node, Op, Position},
+    machine::Machine,
+    ExecStats,
+};
+
+/// Whether the JIT implements every setting in `config`. Other settings run
+/// on the bytecode VM.
+pub fn supports(config: &Config) -> bool {
+    config.cell_width == CellWidth::Bits8
+        && config.overflow == Overflow::Wrap
+        && config.tape.boundary == Boundary::Error
+        && config.step_limit.is_none()
+}
+
+/// Compiles `module` to machine code and runs it. Returns `None` if the
+/// module cannot be compiled, e.g. because an offset does not fit in 32
+/// bits.
+pub fn execute<R: Read, W: Write>(
+    module: &Module,
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+) -> Option<Result<ExecStats, RuntimeError>> {
+    let mut assembler = Assembler::new(
+        output_callback::<R, W> as *const () as usize,
+        input_callback::<R, W> as *const () as usize,
+    );
+    assembler.body(&module.body)?;
+    let Assembler {
+        code, positions, ..
+    } = assembler.finish();
+    let code = Code::new(&code)?;
+
+    let mut machine: Machine<R, W, u8> = Machine::new(input, output, config);
+    let cells = machine.tape.cells_mut();
+    let range = cells.as_mut_ptr_range();
+    let mut context = Context {
+        machine: &mut machine,
+        positions: &positions,
+        error: None,
+    };
+    // SAFETY: the code only touches cells in `range`, which it checks before
+    // every access, and passes `context` back to the callbacks unchanged.
+    let status = unsafe {
+        (code.entry())(
+            range.start,
+            range.end,
+            &mut context as *mut Context<R, W> as *mut c_void,
+        )
+    };
+    let error = context.error.take();
+
+    let end = module
+        .body
+        .last()
+        .map_or(Position { line: 1, column: 1 }, |node| node.position);
+    let result = match Fault::decode(status) {
+        None => machine.finish(end),
+        Some((fault, site)) => {
+            let position = positions[site];
+            Err(match fault {
+                Fault::Underflow => RuntimeError::PointerUnderflow { position },
+                Fault::Overflow => RuntimeError::PointerOverflow { position },
+                Fault::Callback => error.expect("failed callbacks store their error"),
+            })
+        }
+    };
+    Some(result)
+}
+
+/// Why generated code stopped early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    Underflow = 1,
+    Overflow = 2,
+    /// An I/O callback failed and left its error in the [`Context`].
+    Callback = 3,
+}
+
+impl Fault {
+    fn encode(self, site: usize) -> u32 {
+        (site as u32) << 2 | self as u32
+    }
+
+    fn decode(status: u32) -> Option<(Fault, usize)> {
+        let fault = match status & 3 {
+            0 => return None,
+            1 => Fault::Underflow,
+            2 => Fault::Overflow,
+            _ => Fault::Callback,
+        };
+        Some((fault, (status >> 2) as usize))
+    }
+}
+
+/// What the callbacks need to perform I/O on behalf of generated code.
+struct Context<'m, 'a, R: Read, W: Write> {
+    machine: &'m mut Machine<'a, R, W, u8>,
+    /// The source position of every site, for error reporting.
+    positions: &'m [Position],
+    error: Option<RuntimeError>,
+}
+
+extern "sysv64" fn output_callback<R: Read, W: Write>(
+    context: *mut c_void,
+    byte: u32,
+    site: u32,
+) -> u32 {
+    // SAFETY: generated code passes back the pointer given to it by
+    // `execute`, which outlives the call.
+    let context = unsafe { &mut *(context as *mut Context<R, W>) };
+    let position = context.positions[site as usize];
+    match context.machine.write(byte as u8, position) {
+        Ok(()) => 0,
+        Err(err) => {
+            context.error = Some(err);
+            1
+        }
+    }
+}
+
+extern "sysv64" fn input_callback<R: Read, W: Write>(
+    context: *mut c_void,
+    cell: *mut u8,
+    site: u32,
+) -> u32 {
+    // SAFETY: as for `output_callback`; `cell` is the current cell, which
+    // generated code has bounds checked.
+    let (context, cell) = unsafe { (&mut *(context as *mut Context<R, W>), &mut *cell) };
+    let position = context.positions[site as usize];
+    match context.machine.read(position) {
+        Ok(Some(byte)) => *cell = byte,
+        Ok(None) => match context.machine.config.eof {
+            EofBehavior::Unchanged => (),
+            EofBehavior::Zero => *cell = 0,
+            EofBehavior::MinusOne => *cell = u8::MAX,
+        },
+        Err(err) => {
+            context.error = Some(err);
+            return 1;
+        }
+    }
+    0
+}
+
+type Entry = unsafe extern "sysv64" fn(*mut u8, *mut u8, *mut c_void) -> u32;
+
+/// Machine code in an executable mapping.
+struct Code {
+    memory: *mut c_void,
+    length: usize,
+}
+
+impl Code {
+    fn new(code: &[u8]) -> Option<Code> {
+        let length = code.len();
+        // SAFETY: a fresh anonymous mapping is written only through its own
+        // pointer, then made read-only and executable before it is called.
+        unsafe {
+            let memory = libc::mmap(
+                ptr::null_mut(),
+                length,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if memory == libc::MAP_FAILED {
+                return None;
+            }
+            let code_memory = Code { memory, length };
+            ptr::copy_nonoverlapping(code.as_ptr(), memory as *mut u8, length);
+            if libc::mprotect(memory, length, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return None;
+            }
+            Some(code_memory)
+        }
+    }
+
+    fn entry(&self) -> Entry {
+        // SAFETY: the mapping starts with a function following `Entry`.
+        unsafe { std::mem::transmute::<*mut c_void, Entry>(self.memory) }
+    }
+}
+
+impl Drop for Code {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created by `Code::new` and is not used
+        // after this.
+        unsafe {
+            libc::munmap(self.memory, self.length);
+        }
+    }
+}
+
+/// Emits x86-64 machine code for IR nodes.
+struct Assembler {
+    code: Vec<u8>,
+    /// The position of every node that can fail.
+    positions: Vec<Position>,
+    /// Jumps to patch with the address of a stub returning a fault code.
+    faults: Vec<(usize, u32)>,
+    output: usize,
+    input: usize,
+}
+
+impl Assembler {
+    fn new(output: usize, input: usize) -> Assembler {
+        let mut assembler = Assembler {
+            code: Vec::new(),
+            positions: Vec::new(),
+            faults: Vec::new(),
+            output,
+            input,
+        };
+        assembler.emit(&[
+            0x53, // push rbx
+            0x41, 0x54, // push r12
+            0x41, 0x55, // push r13
+            0x41, 0x56, // push r14
+            0x41, 0x57, // push r15, keeping the stack 16-byte aligned
+            0x48, 0x89, 0xfb, // mov rbx, rdi
+            0x49, 0x89, 0xfc, // mov r12, rdi
+            0x49, 0x89, 0xf5, // mov r13, rsi
+            0x49, 0x89, 0xd6, // mov r14, rdx
+        ]);
+        assembler
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.emit(&value.to_le_bytes());
+    }
+
+    fn site(&mut self, position: Position) -> u32 {
+        self.positions.push(position);
+        (self.positions.len() - 1) as u32
+    }
+
+    /// Emits a 32-bit displacement to patch later, returning the offset just
+    /// past it.
+    fn displacement(&mut self) -> usize {
+        self.emit_u32(0);
+        self.code.len()
+    }
+
+    /// Emits a conditional jump to patch later.
+    fn jump(&mut self, condition: u8) -> usize {
+        self.emit(&[0x0f, condition]);
+        self.displacement()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let displacement = target as i64 - at as i64;
+        self.code[at - 4..at].copy_from_slice(&(displacement as i32).to_le_bytes());
+    }
+
+    fn fault(&mut self, condition: u8, fault: Fault, site: u32) {
+        let at = self.jump(condition);
+        self.faults.push((at, fault.encode(site as usize)));
+    }
+
+    /// Faults unless the address in `rax` is within the tape.
+    fn check_rax(&mut self, offset: isize, site: u32) {
+        if offset < 0 {
+            self.emit(&[0x48, 0x39, 0xd8]); // cmp rax, rbx
+            self.fault(JB, Fault::Underflow, site);
+        } else {
+            self.emit(&[0x4c, 0x39, 0xe8]); // cmp rax, r13
+            self.fault(JAE, Fault::Overflow, site);
+        }
+    }
+
+    fn compare_zero(&mut self) {
+        self.emit(&[0x41, 0x80, 0x3c, 0x24, 0x00]); // cmp byte [r12], 0
+    }
+
+    fn call(&mut self, function: usize, site: u32) {
+        self.emit(&[0xba]); // mov edx, site
+        self.emit_u32(site);
+        self.emit(&[0x48, 0xb8]); // mov rax, function
+        self.emit(&(function as u64).to_le_bytes());
+        self.emit(&[0xff, 0xd0]); // call rax
+        self.emit(&[0x85, 0xc0]); // test eax, eax
+        self.fault(JNZ, Fault::Callback, site);
+    }
+
+    fn shift(&mut self, offset: isize, site: u32) -> Option<()> {
+        let offset32 = i32::try_from(offset).ok()?;
+        self.emit(&[0x49, 0x81, 0xc4]); // add r12, offset
+        self.emit_u32(offset32 as u32);
+        self.emit(&[0x4c, 0x89, 0xe0]); // mov rax, r12
+        self.check_rax(offset, site);
+        Some(())
+    }
+
+    fn body(&mut self, body: &[Node]) -> Option<()> {
+        for node in body {
+            match &node.op {
+                Op::Add(amount) => {
+                    // add byte [r12], amount
+                    self.emit(&[0x41, 0x80, 0x04, 0x24, *amount as u8]);
+                }
+                Op::Move(offset) => {
+                    let site = self.site(node.position);
+                    self.shift(*offset, site)?;
+                }
+                Op::Output => {
+                    let site = self.site(node.position);
+                    self.emit(&[0x4c, 0x89, 0xf7]); // mov rdi, r14
+                    self.emit(&[0x41, 0x0f, 0xb6, 0x34, 0x24]); // movzx esi, byte [r12]
+                    self.call(self.output, site);
+                }
+                Op::Input => {
+                    let site = self.site(node.position);
+                    self.emit(&[0x4c, 0x89, 0xf7]); // mov rdi, r14
+                    self.emit(&[0x4c, 0x89, 0xe6]); // mov rsi, r12
+                    self.call(self.input, site);
+                }
+                Op::Loop(inner) => {
+                    self.compare_zero();
+                    let exit = self.jump(JZ);
+                    let start = self.code.len();
+                    self.body(inner)?;
+                    self.compare_zero();
+                    let back = self.jump(JNZ);
+                    self.patch(back, start);
+                    let end = self.code.len();
+                    self.patch(exit, end);
+                }
+                Op::Clear => {
+                    self.emit(&[0x41, 0xc6, 0x04, 0x24, 0x00]); // mov byte [r12], 0
+                }
+                Op::Scan(stride) => {
+                    let site = self.site(node.position);
+                    let head = self.code.len();
+                    self.compare_zero();
+                    let exit = self.jump(JZ);
+                    self.shift(*stride, site)?;
+                    self.emit(&[0xe9]); // jmp head
+                    let back = self.displacement();
+                    self.patch(back, head);
+                    let end = self.code.len();
+                    self.patch(exit, end);
+                }
+                Op::Mul { offset, factor } => {
+                    let site = self.site(node.position);
+                    let offset32 = i32::try_from(*offset).ok()?;
+                    self.compare_zero();
+                    let skip = self.jump(JZ);
+                    // lea rax, [r12 + offset]
+                    self.emit(&[0x49, 0x8d, 0x84, 0x24]);
+                    self.emit_u32(offset32 as u32);
+                    self.check_rax(*offset, site);
+                    self.emit(&[0x41, 0x0f, 0xb6, 0x0c, 0x24]); // movzx ecx, byte [r12]
+                    self.emit(&[0x69, 0xc9]); // imul ecx, ecx, factor
+                    self.emit_u32(*factor as i8 as i32 as u32);
+                    self.emit(&[0x00, 0x08]); // add byte [rax], cl
+                    let end = self.code.len();
+                    self.patch(skip, end);
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Emits the epilogue and the fault stubs.
+    fn finish(mut self) -> Assembler {
+        self.emit(&[0x31, 0xc0]); // xor eax, eax
+        let epilogue = self.code.len();
+        self.emit(&[
+            0x41, 0x5f, // pop r15
+            0x41, 0x5e, // pop r14
+            0x41, 0x5d, // pop r13
+            0x41, 0x5c, // pop r12
+            0x5b, // pop rbx
+            0xc3, // ret
+        ]);
+        for (at, status) in std::mem::take(&mut self.faults) {
+            let stub = self.code.len();
+            self.patch(at, stub);
+            self.emit(&[0xb8]); // mov eax, status
+            self.emit_u32(status);
+            self.emit(&[0xe9]); // jmp epilogue
+            let back = self.displacement();
+            self.patch(back, epilogue);
+        }
+        self
+    }
+}
+
+const JB: u8 = 0x82;
+const JAE: u8 = 0x83;
+const JZ: u8 = 0x84;
+const JNZ: u8 = 0x85;
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::optimize::{optimize, Passes};
+
+    fn run(
+        source: &str,
+        input: &[u8],
+        config: &Config,
+    ) -> (Vec<u8>, Result<ExecStats, RuntimeError>) {
+        let mut module = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut module, &Passes::level(2), config);
+        let mut output = Vec::new();
+        let result = execute(&module, &mut &input[..], &mut output, config).unwrap();
+        (output, result)
+    }
+
+    #[test]
+    fn samples() {
+        let samples: [(&str, &str, &[u8], &[u8]); 6] = include!("../../samples/samples.rs");
+        for (name, source, input, expected) in samples {
+            let (output, result) = run(source, input, &Config::default());
+            let stats = result.unwrap();
+            assert_eq!(output, expected, "{}", name);
+            assert_eq!(stats.input_bytes, input.len() as u64);
+            assert_eq!(stats.output_bytes, expected.len() as u64);
+        }
+    }
+
+    #[test]
+    fn eof_behavior() {
+        for (eof, expected) in [
+            (EofBehavior::Unchanged, 7),
+            (EofBehavior::Zero, 0),
+            (EofBehavior::MinusOne, 255),
+        ] {
+            let config = Config {
+                eof,
+                ..Config::default()
+            };
+            let (output, result) = run("+++++++,.", b"", &config);
+            result.unwrap();
+            assert_eq!(output, [expected]);
+        }
+    }
+
+    #[test]
+    fn faults() {
+        let (output, result) = run(".>+[>+]", b"", &Config::default());
+        assert_eq!(output, [0]);
+        assert!(matches!(
+            result,
+            Err(RuntimeError::PointerOverflow {
+                position: Position { line: 1, column: 5 }
+            })
+        ));
+        let (_, result) = run("+\n[<+>-]", b"", &Config::default());
+        assert!(matches!(
+            result,
+            Err(RuntimeError::PointerUnderflow {
+                position: Position { line: 2, column: 1 }
+            })
+        ));
+    }
+
+    #[test]
+    fn output_errors() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let module = Module::parse(&"+\n+.".chars().collect::<Vec<_>>()).unwrap();
+        let result = execute(&module, &mut io::empty(), &mut Closed, &Config::default()).unwrap();
+        assert!(matches!(
+            result,
+            Err(RuntimeError::Io {
+                position: Position { line: 2, column: 2 },
+                ..
+            })
+        ));
+    }
+}
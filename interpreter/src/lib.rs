@@ -1,54 +1,154 @@
+pub mod bytecode;
+pub mod config;
+pub mod error;
+pub mod ir;
+#[cfg(all(target_arch = "x86_64", unix))]
+mod jit;
+pub mod lint;
+mod machine;
+pub mod optimize;
+mod output;
 mod program;
 mod tape;
 
 use anyhow::Context;
+use bytecode::Bytecode;
+use config::{CellWidth, Config};
+use error::RuntimeError;
+use ir::{Module, Position};
+use machine::{located, Machine};
 use program::Program;
 use std::{
     fs::File,
     io::{Read, Write},
     path::Path,
 };
-use tape::Tape;
+use tape::Cell;
+
+/// Counters collected while a program runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecStats {
+    /// Instructions executed: source commands for [`interpret`], bytecode
+    /// instructions, including every loop test, for [`execute`].
+    pub steps: u64,
+    /// Bytes consumed from the input stream.
+    pub input_bytes: u64,
+    /// Bytes written to the output stream, after any text decoding.
+    pub output_bytes: u64,
+    /// Peak memory allocated for tape cells, in bytes.
+    pub tape_bytes: u64,
+}
+
+/// Runs BF source directly, command by command, reading `,` from `input`
+/// and writing `.` to `output`.
+pub fn interpret<R: Read, W: Write>(
+    content: Vec<char>,
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+) -> Result<ExecStats, RuntimeError> {
+    let program = Program::new(content)?;
+    match config.cell_width {
+        CellWidth::Bits8 => interpret_cells::<R, W, u8>(program, input, output, config),
+        CellWidth::Bits16 => interpret_cells::<R, W, u16>(program, input, output, config),
+        CellWidth::Bits32 => interpret_cells::<R, W, u32>(program, input, output, config),
+        CellWidth::Bits64 => interpret_cells::<R, W, u64>(program, input, output, config),
+    }
+}
 
-pub fn interpret<W: Write>(content: Vec<char>, stream: &mut W) {
-    let mut program = Program::new(content);
-    let mut tape = Tape::new();
+fn interpret_cells<R: Read, W: Write, C: Cell>(
+    mut program: Program,
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+) -> Result<ExecStats, RuntimeError> {
+    let mut machine: Machine<R, W, C> = Machine::new(input, output, config);
 
     while !program.finished() {
+        let position = program.position();
+        machine.step(position)?;
         match program.command() {
-            '+' => tape.inc(),
-            '-' => tape.dec(),
-            '>' => tape.next(),
-            '<' => tape.prev(),
-            '.' => {
-                stream
-                    .write(format!("{}", tape.value() as char).as_bytes())
-                    .unwrap();
-            }
-            ',' => tape.read_value(),
-            '[' if tape.zero() => program.fast_forward(1),
-            ']' if tape.not_zero() => program.rewind(1),
+            '+' => located(machine.tape.inc(), position)?,
+            '-' => located(machine.tape.dec(), position)?,
+            '>' => located(machine.tape.next(), position)?,
+            '<' => located(machine.tape.prev(), position)?,
+            '.' => machine.output(position)?,
+            ',' => machine.input(position)?,
+            '[' if machine.tape.zero() => program.jump(),
+            ']' if machine.tape.not_zero() => program.jump(),
             _ => (),
         }
 
         program.forward();
     }
+    machine.finish(program.end())
+}
+
+/// Runs a parsed, and possibly optimized, module on the bytecode VM.
+pub fn execute<R: Read, W: Write>(
+    module: &Module,
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+) -> Result<ExecStats, RuntimeError> {
+    match config.cell_width {
+        CellWidth::Bits8 => execute_cells::<R, W, u8>(module, input, output, config),
+        CellWidth::Bits16 => execute_cells::<R, W, u16>(module, input, output, config),
+        CellWidth::Bits32 => execute_cells::<R, W, u32>(module, input, output, config),
+        CellWidth::Bits64 => execute_cells::<R, W, u64>(module, input, output, config),
+    }
+}
+
+fn execute_cells<R: Read, W: Write, C: Cell>(
+    module: &Module,
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+) -> Result<ExecStats, RuntimeError> {
+    let bytecode = Bytecode::compile(module);
+    let mut machine: Machine<R, W, C> = Machine::new(input, output, config);
+    machine.run(&bytecode)?;
+    let end = module
+        .body
+        .last()
+        .map_or(Position { line: 1, column: 1 }, |node| node.position);
+    machine.finish(end)
+}
+
+/// Runs a module as x86-64 machine code generated at runtime. Falls back to
+/// [`execute`] for settings the JIT does not implement: cells wider than 8
+/// bits, overflow policies other than wrap, tape boundaries other than error
+/// and step limits, and on other platforms. Steps are not counted by the
+/// JIT, so `steps` stays 0.
+pub fn execute_jit<R: Read, W: Write>(
+    module: &Module,
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+) -> Result<ExecStats, RuntimeError> {
+    #[cfg(all(target_arch = "x86_64", unix))]
+    if jit::supports(config) {
+        if let Some(result) = jit::execute(module, input, output, config) {
+            return result;
+        }
+    }
+    execute(module, input, output, config)
 }
 
 pub fn read_program(path: &Path) -> anyhow::Result<Vec<char>> {
     let mut buffer = String::new();
 
-    let mut file = File::open(path).expect("Could not open the file!");
+    let mut file = File::open(path).with_context(|| format!("open {}", path.display()))?;
     file.read_to_string(&mut buffer).context("read from file")?;
     Ok(buffer.chars().collect())
 }
 
 #[cfg(test)]
-
 mod tests {
-    use std::io::Cursor;
+    use std::io::{self, Cursor};
 
     use super::*;
+    use config::{EofBehavior, Overflow, TextMode};
 
     #[test]
     fn bangbang() {
@@ -59,10 +159,293 @@ mod tests {
 +++
 ..";
         let mut stream = Cursor::new(vec![0, 200]);
-        interpret::<Cursor<Vec<u8>>>(content.chars().collect(), &mut stream);
+        interpret::<io::Empty, Cursor<Vec<u8>>>(
+            content.chars().collect(),
+            &mut io::empty(),
+            &mut stream,
+            &Config::default(),
+        )
+        .unwrap();
         assert_eq!(stream.get_ref(), "!!".as_bytes());
     }
 
+    #[test]
+    fn optimized_levels() {
+        let content: Vec<char> = include_str!("../../samples/hello_world.bf")
+            .chars()
+            .collect();
+        for level in 0..=2 {
+            let mut module = Module::parse(&content).unwrap();
+            optimize::optimize(
+                &mut module,
+                &optimize::Passes::level(level),
+                &Config::default(),
+            );
+            let mut stream = Cursor::new(Vec::new());
+            execute(&module, &mut io::empty(), &mut stream, &Config::default()).unwrap();
+            assert_eq!(
+                stream.get_ref().as_slice(),
+                include_bytes!("../../samples/hello_world.bf.out")
+            );
+        }
+    }
+
+    #[test]
+    fn output_is_byte_exact() {
+        // 200 and 255 would each become two bytes if formatted as chars.
+        let content: Vec<char> = format!("-.{}.", "-".repeat(55)).chars().collect();
+        let mut stream = Cursor::new(Vec::new());
+        interpret(
+            content.clone(),
+            &mut io::empty(),
+            &mut stream,
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(stream.get_ref(), &[255, 200]);
+
+        let config = Config {
+            text_mode: Some(TextMode::Latin1),
+            ..Config::default()
+        };
+        let mut stream = Cursor::new(Vec::new());
+        let stats = interpret(content, &mut io::empty(), &mut stream, &config).unwrap();
+        assert_eq!(stream.get_ref(), "ÿÈ".as_bytes());
+        assert_eq!(stats.output_bytes, 4);
+    }
+
+    #[test]
+    fn input_from_reader() {
+        let mut input = Cursor::new(b"ab".to_vec());
+        let mut output = Vec::new();
+        let stats = interpret(
+            ",.,.,.".chars().collect(),
+            &mut input,
+            &mut output,
+            &Config::default(),
+        )
+        .unwrap();
+        // The third `,` hits the end of input and keeps the cell.
+        assert_eq!(output, b"abb");
+        assert_eq!(stats.input_bytes, 2);
+    }
+
+    #[test]
+    fn eof_behavior() {
+        for (eof, expected) in [
+            (EofBehavior::Unchanged, 7),
+            (EofBehavior::Zero, 0),
+            (EofBehavior::MinusOne, 255),
+        ] {
+            let config = Config {
+                eof,
+                ..Config::default()
+            };
+            let content: Vec<char> = "+++++++,.".chars().collect();
+            let mut output = Vec::new();
+            interpret(content.clone(), &mut io::empty(), &mut output, &config).unwrap();
+            let module = Module::parse(&content).unwrap();
+            execute(&module, &mut io::empty(), &mut output, &config).unwrap();
+            assert_eq!(output, [expected, expected]);
+        }
+    }
+
+    #[test]
+    fn wide_cells() {
+        // 256 wraps to 0 in 8-bit cells, so the loop is skipped there.
+        let content: Vec<char> = format!("{}[[-]>+<]>.,.", "+".repeat(256)).chars().collect();
+        for (cell_width, expected) in [
+            (CellWidth::Bits8, [0, 255]),
+            (CellWidth::Bits16, [1, 255]),
+            (CellWidth::Bits64, [1, 255]),
+        ] {
+            let config = Config {
+                cell_width,
+                eof: EofBehavior::MinusOne,
+                ..Config::default()
+            };
+            let mut output = Vec::new();
+            interpret(content.clone(), &mut io::empty(), &mut output, &config).unwrap();
+            let mut module = Module::parse(&content).unwrap();
+            optimize::optimize(&mut module, &optimize::Passes::level(2), &config);
+            execute(&module, &mut io::empty(), &mut output, &config).unwrap();
+            assert_eq!(output, [expected, expected].concat());
+        }
+    }
+
+    #[test]
+    fn samples_with_input() {
+        let samples: [(&str, &[u8], &[u8]); 2] = [
+            (
+                include_str!("../../samples/factor.bf"),
+                include_bytes!("../../samples/factor.bf.in"),
+                include_bytes!("../../samples/factor.bf.out"),
+            ),
+            (
+                include_str!("../../samples/life.bf"),
+                include_bytes!("../../samples/life.bf.in"),
+                include_bytes!("../../samples/life.bf.out"),
+            ),
+        ];
+        for (content, input, expected) in samples {
+            let mut module = Module::parse(&content.chars().collect::<Vec<_>>()).unwrap();
+            optimize::optimize(&mut module, &optimize::Passes::level(2), &Config::default());
+            let mut output = Vec::new();
+            execute(&module, &mut &input[..], &mut output, &Config::default()).unwrap();
+            assert_eq!(output, expected);
+        }
+    }
+
+    /// The VM, at every optimization level, agrees with the legacy engine.
+    #[test]
+    fn engines_agree() {
+        let samples: [(&str, &[u8]); 5] = [
+            (include_str!("../../samples/hello_world.bf"), b""),
+            (include_str!("../../samples/bottles.bf"), b""),
+            (include_str!("../../samples/bangbang.bf"), b""),
+            (
+                include_str!("../../samples/factor.bf"),
+                include_bytes!("../../samples/factor.bf.in"),
+            ),
+            (",[.[-],]", b"echo"),
+        ];
+        for (content, input) in samples {
+            let content: Vec<char> = content.chars().collect();
+            let mut expected = Vec::new();
+            let legacy = interpret(
+                content.clone(),
+                &mut &input[..],
+                &mut expected,
+                &Config::default(),
+            )
+            .unwrap();
+            for level in 0..=2 {
+                let mut module = Module::parse(&content).unwrap();
+                optimize::optimize(
+                    &mut module,
+                    &optimize::Passes::level(level),
+                    &Config::default(),
+                );
+                let mut output = Vec::new();
+                let stats =
+                    execute(&module, &mut &input[..], &mut output, &Config::default()).unwrap();
+                assert_eq!(output, expected);
+                if level == 0 {
+                    // Both count `[` once per entry and `]` once per iteration.
+                    assert_eq!(stats, legacy);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pointer_underflow() {
+        let content = include_str!("../../samples/warning_out_of_bounds.bf");
+        let mut stream = Cursor::new(Vec::new());
+        let err = interpret(
+            content.chars().collect(),
+            &mut io::empty(),
+            &mut stream,
+            &Config::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            RuntimeError::PointerUnderflow {
+                position: Position { line: 1, column: 1 }
+            }
+        ));
+
+        let module = Module::parse(&content.chars().collect::<Vec<_>>()).unwrap();
+        let err = execute(&module, &mut io::empty(), &mut stream, &Config::default()).unwrap_err();
+        assert!(matches!(err, RuntimeError::PointerUnderflow { .. }));
+    }
+
+    #[test]
+    fn pointer_overflow() {
+        let content: Vec<char> = "+[>+]".chars().collect();
+        let mut stream = Cursor::new(Vec::new());
+        let err =
+            interpret(content, &mut io::empty(), &mut stream, &Config::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            RuntimeError::PointerOverflow {
+                position: Position { line: 1, column: 3 }
+            }
+        ));
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let content: Vec<char> = format!(">\n{}.>-.", "+".repeat(300)).chars().collect();
+        let saturate = Config {
+            overflow: Overflow::Saturate,
+            ..Config::default()
+        };
+        let mut output = Vec::new();
+        interpret(content.clone(), &mut io::empty(), &mut output, &saturate).unwrap();
+        let mut module = Module::parse(&content).unwrap();
+        optimize::optimize(&mut module, &optimize::Passes::level(2), &saturate);
+        execute(&module, &mut io::empty(), &mut output, &saturate).unwrap();
+        assert_eq!(output, [255, 0, 255, 0]);
+
+        let trap = Config {
+            overflow: Overflow::Error,
+            ..Config::default()
+        };
+        let err = interpret(content.clone(), &mut io::empty(), &mut output, &trap).unwrap_err();
+        let expected = Position {
+            line: 2,
+            column: 256,
+        };
+        assert!(matches!(
+            err,
+            RuntimeError::CellOverflow { position, cell: 1, previous: 255 } if position == expected
+        ));
+        assert_eq!(err.to_string(), "cell 1 overflowed from 255 at 2:256");
+        let mut module = Module::parse(&content).unwrap();
+        optimize::optimize(&mut module, &optimize::Passes::level(2), &trap);
+        let err = execute(&module, &mut io::empty(), &mut output, &trap).unwrap_err();
+        assert!(matches!(
+            err,
+            RuntimeError::CellOverflow { position, cell: 1, previous: 255 } if position == expected
+        ));
+    }
+
+    #[test]
+    fn step_limit() {
+        let config = Config {
+            step_limit: Some(100),
+            ..Config::default()
+        };
+        let mut stream = Cursor::new(Vec::new());
+        let err = interpret(
+            "+[]".chars().collect(),
+            &mut io::empty(),
+            &mut stream,
+            &config,
+        )
+        .unwrap_err();
+        assert!(matches!(err, RuntimeError::StepLimit { limit: 100, .. }));
+
+        let stats = interpret(
+            "+++.".chars().collect(),
+            &mut io::empty(),
+            &mut stream,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            stats,
+            ExecStats {
+                steps: 4,
+                input_bytes: 0,
+                output_bytes: 1,
+                tape_bytes: 30000,
+            }
+        );
+    }
+
     #[test]
     fn bottles() {
         let content = ">+++++++++[<+++++++++++>-]<[>[-]>[-]<<[>+>+<<-]>>[<<+>>-]>>>
@@ -111,7 +494,13 @@ mod tests {
 ..>+++++++++[<---------->-]<-----.---.+++.---.[-]<<<]
 ";
         let mut stream = Cursor::new(vec![0, 200]);
-        interpret(content.chars().collect(), &mut stream);
+        interpret(
+            content.chars().collect(),
+            &mut io::empty(),
+            &mut stream,
+            &Config::default(),
+        )
+        .unwrap();
         let expected = "99 Bottles of beer on the wall
 99 Bottles of beer
 Take one down and pass it around
@@ -0,0 +1,391 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+
+use crate::ir::{Module, Node, Op, Position};
+
+/// A static check run by `bf --lint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    /// A loop entered when the current cell is always zero, e.g. at program
+    /// start or right after another loop.
+    DeadLoop,
+    /// Code after the last `.` or `,`, which cannot change what the program
+    /// reads or writes.
+    NoEffect,
+    /// A move outside any loop that takes the pointer left of cell 0.
+    NegativeMove,
+    /// Adjacent `+-`, `-+`, `<>` or `><`.
+    Cancel,
+    /// A loop whose body moves the pointer, so each iteration starts on a
+    /// different cell. Scans such as `[>]` are exempt.
+    LoopDrift,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::DeadLoop,
+        Lint::NoEffect,
+        Lint::NegativeMove,
+        Lint::Cancel,
+        Lint::LoopDrift,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::DeadLoop => "dead-loop",
+            Lint::NoEffect => "no-effect",
+            Lint::NegativeMove => "negative-move",
+            Lint::Cancel => "cancel",
+            Lint::LoopDrift => "loop-drift",
+        }
+    }
+
+    /// The severity used unless overridden in [`Levels`].
+    fn default_severity(&self) -> Severity {
+        match self {
+            Lint::NegativeMove => Severity::Error,
+            Lint::DeadLoop | Lint::NoEffect | Lint::Cancel | Lint::LoopDrift => Severity::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Lint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Lint::ALL.iter().find(|lint| lint.name() == s) {
+            Some(lint) => Ok(*lint),
+            None => bail!(
+                "unknown lint '{}', expected one of: {}",
+                s,
+                Lint::ALL.map(|lint| lint.name()).join(", ")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Not reported.
+    Allow,
+    Warn,
+    /// Reported, and `bf --lint` fails.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Allow => write!(f, "allow"),
+            Severity::Warn => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// The severity of every lint.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Levels {
+    overrides: Vec<(Lint, Severity)>,
+}
+
+impl Levels {
+    pub fn set(&mut self, lint: Lint, severity: Severity) {
+        self.overrides.retain(|(overridden, _)| *overridden != lint);
+        self.overrides.push((lint, severity));
+    }
+
+    pub fn severity(&self, lint: Lint) -> Severity {
+        self.overrides
+            .iter()
+            .find(|(overridden, _)| *overridden == lint)
+            .map_or(lint.default_severity(), |(_, severity)| *severity)
+    }
+}
+
+/// A problem found by [`lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub lint: Lint,
+    pub severity: Severity,
+    pub position: Position,
+    pub message: String,
+}
+
+impl Finding {
+    /// The label shown before the message, e.g. `warning[dead-loop]`.
+    pub fn label(&self) -> String {
+        format!("{}[{}]", self.severity, self.lint)
+    }
+}
+
+/// Checks an unoptimized module, returning every finding that is not
+/// allowed, in source order.
+pub fn lint(module: &Module, levels: &Levels) -> Vec<Finding> {
+    let mut linter = Linter {
+        levels,
+        findings: Vec::new(),
+    };
+    let start = State {
+        pointer: Some(0),
+        zero: true,
+        pristine: true,
+        reached: true,
+    };
+    linter.body(&module.body, start);
+    linter.trailing(&module.body);
+    linter
+        .findings
+        .sort_by_key(|finding| (finding.position.line, finding.position.column));
+    linter.findings
+}
+
+/// What is known about the tape at a point in the program.
+#[derive(Debug, Clone, Copy)]
+struct State {
+    /// The pointer, while every path so far moved it by the same amount.
+    pointer: Option<isize>,
+    /// The current cell is zero.
+    zero: bool,
+    /// No cell has been written yet, so every cell is zero.
+    pristine: bool,
+    /// Every run of the program gets here, i.e. this is not inside a loop.
+    reached: bool,
+}
+
+struct Linter<'a> {
+    levels: &'a Levels,
+    findings: Vec<Finding>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, lint: Lint, position: Position, message: String) {
+        let severity = self.levels.severity(lint);
+        if severity != Severity::Allow {
+            self.findings.push(Finding {
+                lint,
+                severity,
+                position,
+                message,
+            });
+        }
+    }
+
+    fn body(&mut self, body: &[Node], mut state: State) -> State {
+        for (index, node) in body.iter().enumerate() {
+            if let Some(previous) = index.checked_sub(1).map(|index| &body[index]) {
+                self.cancel(previous, node);
+            }
+            match &node.op {
+                Op::Add(_) | Op::Mul { .. } | Op::Input => {
+                    state.zero = false;
+                    state.pristine = false;
+                }
+                Op::Move(offset) => {
+                    state.pointer = state.pointer.map(|pointer| pointer + offset);
+                    if state.reached && state.pointer.is_some_and(|pointer| pointer < 0) {
+                        self.report(
+                            Lint::NegativeMove,
+                            node.position,
+                            "pointer moves left of cell 0".to_string(),
+                        );
+                        // Report the first move only.
+                        state.pointer = None;
+                    }
+                    state.zero = state.pristine;
+                }
+                Op::Output => (),
+                Op::Clear => state.zero = true,
+                Op::Scan(_) => {
+                    state.pointer = None;
+                    state.zero = true;
+                }
+                Op::Loop(inner) => {
+                    state = self.looped(node.position, inner, state);
+                }
+            }
+        }
+        state
+    }
+
+    fn looped(&mut self, position: Position, inner: &[Node], state: State) -> State {
+        if state.zero {
+            self.report(
+                Lint::DeadLoop,
+                position,
+                "loop can never run, the current cell is always zero here".to_string(),
+            );
+            return state;
+        }
+
+        let entry = State {
+            zero: false,
+            reached: false,
+            ..state
+        };
+        self.body(inner, entry);
+        let drift = drift(inner);
+        let scan = inner.iter().all(|node| matches!(node.op, Op::Move(_)));
+        match drift {
+            Some(0) => (),
+            _ if scan => (),
+            Some(drift) => self.report(
+                Lint::LoopDrift,
+                position,
+                format!("loop moves the pointer by {} cells per iteration", drift),
+            ),
+            None => self.report(
+                Lint::LoopDrift,
+                position,
+                "loop moves the pointer by a varying amount".to_string(),
+            ),
+        }
+        State {
+            pointer: state.pointer.filter(|_| drift == Some(0)),
+            zero: true,
+            pristine: false,
+            reached: state.reached,
+        }
+    }
+
+    fn cancel(&mut self, previous: &Node, node: &Node) {
+        let pair = match (&previous.op, &node.op) {
+            (Op::Add(a), Op::Add(b)) if a.signum() == -b.signum() => {
+                if *a > 0 {
+                    "+-"
+                } else {
+                    "-+"
+                }
+            }
+            (Op::Move(a), Op::Move(b)) if a.signum() == -b.signum() => {
+                if *a > 0 {
+                    "><"
+                } else {
+                    "<>"
+                }
+            }
+            _ => return,
+        };
+        self.report(
+            Lint::Cancel,
+            previous.position,
+            format!("`{}` cancels out", pair),
+        );
+    }
+
+    /// Reports the top-level code after the last input or output.
+    fn trailing(&mut self, body: &[Node]) {
+        let start = match body.iter().rposition(|node| io(&node.op)) {
+            Some(last) => last + 1,
+            None => 0,
+        };
+        if let Some(node) = body.get(start) {
+            let message = if start == 0 {
+                "program never reads or writes, so it has no observable effect"
+            } else {
+                "code after the last input or output has no observable effect"
+            };
+            self.report(Lint::NoEffect, node.position, message.to_string());
+        }
+    }
+}
+
+/// The net pointer movement of one pass through `body`, if it is the same
+/// every time.
+fn drift(body: &[Node]) -> Option<isize> {
+    body.iter().try_fold(0, |total, node| match &node.op {
+        Op::Move(offset) => Some(total + offset),
+        Op::Loop(inner) => drift(inner).filter(|drift| *drift == 0).map(|_| total),
+        Op::Scan(_) => None,
+        _ => Some(total),
+    })
+}
+
+fn io(op: &Op) -> bool {
+    match op {
+        Op::Output | Op::Input => true,
+        Op::Loop(inner) => inner.iter().any(|node| io(&node.op)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn findings(source: &str) -> Vec<(Lint, usize, usize)> {
+        let module = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        lint(&module, &Levels::default())
+            .into_iter()
+            .map(|finding| (finding.lint, finding.position.line, finding.position.column))
+            .collect()
+    }
+
+    #[test]
+    fn dead_loops() {
+        assert_eq!(
+            findings("[.]>[.]+[-][.]."),
+            [
+                (Lint::DeadLoop, 1, 1),
+                (Lint::DeadLoop, 1, 5),
+                (Lint::DeadLoop, 1, 12)
+            ]
+        );
+        assert_eq!(findings(",[.]"), []);
+    }
+
+    #[test]
+    fn negative_moves() {
+        assert_eq!(findings("<."), [(Lint::NegativeMove, 1, 1)]);
+        // The loop body might never run.
+        assert_eq!(findings(">,[<-<]."), [(Lint::LoopDrift, 1, 3)]);
+        assert_eq!(findings("+[>+<-]<<."), [(Lint::NegativeMove, 1, 8)]);
+    }
+
+    #[test]
+    fn cancellations() {
+        assert_eq!(
+            findings(",>+-<>."),
+            [(Lint::Cancel, 1, 3), (Lint::Cancel, 1, 5)]
+        );
+    }
+
+    #[test]
+    fn loop_drift() {
+        assert_eq!(findings(",[>,]."), [(Lint::LoopDrift, 1, 2)]);
+        assert_eq!(findings(",[>]<[[<]>,]."), [(Lint::LoopDrift, 1, 6)]);
+        assert_eq!(findings(",[>+<-]."), []);
+    }
+
+    #[test]
+    fn samples() {
+        let dead_code = include_str!("../../samples/warning_dead_code.bf");
+        assert_eq!(findings(dead_code), [(Lint::NoEffect, 6, 3)]);
+        let out_of_bounds = include_str!("../../samples/warning_out_of_bounds.bf");
+        assert_eq!(findings(out_of_bounds), [(Lint::NegativeMove, 1, 1)]);
+        assert_eq!(findings("+++"), [(Lint::NoEffect, 1, 1)]);
+    }
+
+    #[test]
+    fn severity_levels() {
+        let module = Module::parse(&"<+-".chars().collect::<Vec<_>>()).unwrap();
+        let mut levels = Levels::default();
+        levels.set(Lint::Cancel, Severity::Error);
+        levels.set(Lint::NoEffect, Severity::Allow);
+        levels.set(Lint::NegativeMove, Severity::Warn);
+        let found: Vec<String> = lint(&module, &levels)
+            .iter()
+            .map(|finding| finding.label())
+            .collect();
+        assert_eq!(found, ["warning[negative-move]", "error[cancel]"]);
+        assert_eq!("loop-drift".parse::<Lint>().unwrap(), Lint::LoopDrift);
+        assert!("unused".parse::<Lint>().is_err());
+    }
+}
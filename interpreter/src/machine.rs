@@ -0,0 +1,160 @@
+use std::io::{ErrorKind, Read, Write};
+
+use crate::{
+    bytecode::{Bytecode, Instr},
+    config::{Config, EofBehavior, Overflow},
+    error::RuntimeError,
+    ir::Position,
+    output::Output,
+    tape::{Cell, Fault, Tape},
+    ExecStats,
+};
+
+/// Attaches the position of the failing instruction to a tape fault.
+pub fn located(result: Result<(), Fault>, position: Position) -> Result<(), RuntimeError> {
+    result.map_err(|fault| match fault {
+        Fault::Underflow => RuntimeError::PointerUnderflow { position },
+        Fault::Overflow => RuntimeError::PointerOverflow { position },
+        Fault::Cell { index, previous } => RuntimeError::CellOverflow {
+            position,
+            cell: index,
+            previous,
+        },
+    })
+}
+
+/// Tape, output and accounting shared by the engines.
+pub struct Machine<'a, R: Read, W: Write, C: Cell> {
+    pub tape: Tape<C>,
+    input: &'a mut R,
+    output: Output<'a, W>,
+    pub config: &'a Config,
+    stats: ExecStats,
+}
+
+impl<'a, R: Read, W: Write, C: Cell> Machine<'a, R, W, C> {
+    pub fn new(input: &'a mut R, output: &'a mut W, config: &'a Config) -> Machine<'a, R, W, C> {
+        Machine {
+            tape: Tape::new(config),
+            input,
+            output: Output::new(output, config.text_mode),
+            config,
+            stats: ExecStats::default(),
+        }
+    }
+
+    pub fn step(&mut self, position: Position) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.config.step_limit {
+            if self.stats.steps >= limit {
+                return Err(RuntimeError::StepLimit { position, limit });
+            }
+        }
+        self.stats.steps += 1;
+        Ok(())
+    }
+
+    pub fn output(&mut self, position: Position) -> Result<(), RuntimeError> {
+        self.write(self.tape.value().low_byte(), position)
+    }
+
+    pub fn write(&mut self, byte: u8, position: Position) -> Result<(), RuntimeError> {
+        let written = self
+            .output
+            .write(byte)
+            .map_err(|source| RuntimeError::Io { position, source })?;
+        self.stats.output_bytes += written as u64;
+        Ok(())
+    }
+
+    /// Reads one byte into the current cell, applying the configured EOF
+    /// behavior at the end of input.
+    pub fn input(&mut self, position: Position) -> Result<(), RuntimeError> {
+        match self.read(position)? {
+            Some(byte) => self.tape.set_value(C::from_byte(byte)),
+            None => match self.config.eof {
+                EofBehavior::Unchanged => (),
+                EofBehavior::Zero => self.tape.set_value(C::default()),
+                EofBehavior::MinusOne => self.tape.set_value(C::MAX),
+            },
+        }
+        Ok(())
+    }
+
+    /// Reads one byte, or `None` at the end of input.
+    pub fn read(&mut self, position: Position) -> Result<Option<u8>, RuntimeError> {
+        let mut byte = [0];
+        loop {
+            return match self.input.read(&mut byte) {
+                Ok(0) => Ok(None),
+                Ok(_) => {
+                    self.stats.input_bytes += 1;
+                    Ok(Some(byte[0]))
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(source) => Err(RuntimeError::Io { position, source }),
+            };
+        }
+    }
+
+    pub fn finish(mut self, position: Position) -> Result<ExecStats, RuntimeError> {
+        let written = self
+            .output
+            .finish()
+            .map_err(|source| RuntimeError::Io { position, source })?;
+        self.stats.output_bytes += written as u64;
+        self.stats.tape_bytes = self.tape.memory() as u64;
+        Ok(self.stats)
+    }
+
+    /// The amount a `mul` adds to its target: exact, or clamped to the `i64`
+    /// range when the policy cares about overflow.
+    fn product(&self, factor: i64) -> i64 {
+        let value = self.tape.value().as_u64();
+        match self.config.overflow {
+            Overflow::Wrap => (value as i64).wrapping_mul(factor),
+            Overflow::Saturate | Overflow::Error => {
+                (value as i128 * factor as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+            }
+        }
+    }
+
+    /// Runs `bytecode` to completion.
+    pub fn run(&mut self, bytecode: &Bytecode) -> Result<(), RuntimeError> {
+        let Bytecode { code, positions } = bytecode;
+        let mut pc = 0;
+        while let Some(instr) = code.get(pc) {
+            let position = positions[pc];
+            self.step(position)?;
+            match *instr {
+                Instr::Add(amount) => located(self.tape.add(amount), position)?,
+                Instr::Move(offset) => located(self.tape.shift(offset), position)?,
+                Instr::Output => self.output(position)?,
+                Instr::Input => self.input(position)?,
+                Instr::Jz(target) if self.tape.zero() => {
+                    pc = target;
+                    continue;
+                }
+                Instr::Jnz(target) if self.tape.not_zero() => {
+                    pc = target;
+                    continue;
+                }
+                Instr::Jz(_) | Instr::Jnz(_) => (),
+                Instr::Clear => self.tape.set_value(C::default()),
+                Instr::Scan(stride) => {
+                    while self.tape.not_zero() {
+                        located(self.tape.shift(stride), position)?;
+                        self.step(position)?;
+                    }
+                }
+                Instr::Mul { offset, factor } => {
+                    if self.tape.not_zero() {
+                        let amount = self.product(factor);
+                        located(self.tape.add_at(offset, amount), position)?;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::bail;
+
+use crate::{
+    config::{CellWidth, Config, Overflow},
+    ir::{Module, Node, Op},
+};
+
+/// A single IR optimization pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Merge runs of identical commands, e.g. `+++` into `add 3`.
+    Fold,
+    /// Merge adjacent opposite commands, e.g. `+-` or `><`, dropping no-ops.
+    Cancel,
+    /// Replace `[-]` and `[+]` with `clear`.
+    Clear,
+    /// Replace `[>]` and `[<<]` style loops with `scan`.
+    Scan,
+    /// Replace copy and multiply loops such as `[->+>++<<]` with `mul`.
+    Multiply,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 5] = [
+        Pass::Fold,
+        Pass::Cancel,
+        Pass::Clear,
+        Pass::Scan,
+        Pass::Multiply,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Fold => "fold",
+            Pass::Cancel => "cancel",
+            Pass::Clear => "clear",
+            Pass::Scan => "scan",
+            Pass::Multiply => "multiply",
+        }
+    }
+
+    /// The lowest `-O` level that enables this pass.
+    fn level(&self) -> u32 {
+        match self {
+            Pass::Fold | Pass::Cancel => 1,
+            Pass::Clear | Pass::Scan | Pass::Multiply => 2,
+        }
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Pass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Pass::ALL.iter().find(|pass| pass.name() == s) {
+            Some(pass) => Ok(*pass),
+            None => bail!(
+                "unknown pass '{}', expected one of: {}",
+                s,
+                Pass::ALL.map(|pass| pass.name()).join(", ")
+            ),
+        }
+    }
+}
+
+/// The set of passes the optimizer runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passes {
+    enabled: Vec<Pass>,
+}
+
+impl Passes {
+    /// The passes enabled by an `-O` level: 0 runs nothing, 1 folds and
+    /// cancels, 2 additionally recognizes loop idioms.
+    pub fn level(level: u32) -> Passes {
+        Passes {
+            enabled: Pass::ALL
+                .into_iter()
+                .filter(|pass| pass.level() <= level)
+                .collect(),
+        }
+    }
+
+    pub fn enable(&mut self, pass: Pass) {
+        if !self.enabled(pass) {
+            self.enabled.push(pass);
+        }
+    }
+
+    pub fn disable(&mut self, pass: Pass) {
+        self.enabled.retain(|enabled| *enabled != pass);
+    }
+
+    pub fn enabled(&self, pass: Pass) -> bool {
+        self.enabled.contains(&pass)
+    }
+}
+
+/// Rewrites `module` in place with the given passes. Cell arithmetic is
+/// folded modulo the cell width in `config`, and only where the result
+/// matches running the commands one by one under its overflow policy.
+pub fn optimize(module: &mut Module, passes: &Passes, config: &Config) {
+    let optimizer = Optimizer {
+        passes,
+        width: config.cell_width,
+        overflow: config.overflow,
+    };
+    let body = std::mem::take(&mut module.body);
+    module.body = optimizer.body(body);
+}
+
+struct Optimizer<'a> {
+    passes: &'a Passes,
+    width: CellWidth,
+    overflow: Overflow,
+}
+
+impl Optimizer<'_> {
+    fn body(&self, body: Vec<Node>) -> Vec<Node> {
+        let mut result: Vec<Node> = Vec::with_capacity(body.len());
+
+        for node in body {
+            let node = match node.op {
+                Op::Loop(inner) => {
+                    let inner = self.body(inner);
+                    match self.idiom(&inner) {
+                        Some(ops) => {
+                            result.extend(ops.into_iter().map(|op| Node::new(op, node.position)));
+                            continue;
+                        }
+                        None => Node::new(Op::Loop(inner), node.position),
+                    }
+                }
+                _ => node,
+            };
+
+            if let Some(last) = result.last_mut() {
+                if self.merge(last, &node) {
+                    if matches!(last.op, Op::Add(0) | Op::Move(0)) {
+                        result.pop();
+                    }
+                    continue;
+                }
+            }
+            result.push(node);
+        }
+        result
+    }
+
+    /// Merges `node` into `last` if the enabled passes allow it.
+    fn merge(&self, last: &mut Node, node: &Node) -> bool {
+        let allowed = |a: i64, b: i64| {
+            if a.signum() == b.signum() {
+                self.passes.enabled(Pass::Fold)
+            } else {
+                self.passes.enabled(Pass::Cancel)
+            }
+        };
+        // Saturation is monotonic, so a run in one direction may be folded
+        // but `+-` may not cancel. Trapping keeps every add, so the error
+        // names the exact command and value.
+        let arithmetic = |a: i64, b: i64| match self.overflow {
+            Overflow::Wrap => allowed(a, b),
+            Overflow::Saturate => a.signum() == b.signum() && allowed(a, b),
+            Overflow::Error => false,
+        };
+        match (&mut last.op, &node.op) {
+            (Op::Add(a), Op::Add(b)) if arithmetic(*a, *b) => {
+                *a = self.add(*a, *b);
+                true
+            }
+            (Op::Move(a), Op::Move(b)) if allowed(*a as i64, *b as i64) => {
+                *a += *b;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds two amounts the way the tape would apply them in sequence.
+    fn add(&self, a: i64, b: i64) -> i64 {
+        match self.overflow {
+            Overflow::Wrap => self.width.wrap(a.wrapping_add(b)),
+            Overflow::Saturate | Overflow::Error => a.saturating_add(b),
+        }
+    }
+
+    /// Whether `[amount]` always ends with the cell at zero.
+    fn clears(&self, amount: i64) -> bool {
+        match self.overflow {
+            // An odd step is invertible modulo 2^bits, so the cell reaches zero.
+            Overflow::Wrap => amount % 2 != 0,
+            // Counting down stops at zero; counting up sticks at the maximum.
+            Overflow::Saturate => amount < 0,
+            // Only `[-]` reaches zero without stepping past it.
+            Overflow::Error => amount == -1,
+        }
+    }
+
+    /// Recognizes a loop body that can be replaced by straight-line operations.
+    fn idiom(&self, body: &[Node]) -> Option<Vec<Op>> {
+        match body {
+            [Node {
+                op: Op::Add(amount),
+                ..
+            }] if self.passes.enabled(Pass::Clear) && self.clears(*amount) => Some(vec![Op::Clear]),
+            [Node {
+                op: Op::Move(stride),
+                ..
+            }] if self.passes.enabled(Pass::Scan) => Some(vec![Op::Scan(*stride)]),
+            _ if self.passes.enabled(Pass::Multiply) => self.multiply(body),
+            _ => None,
+        }
+    }
+
+    /// Recognizes loops made only of adds and moves that return to the starting
+    /// cell and step it by one, i.e. `cell[offset] += cell[0] * factor`.
+    fn multiply(&self, body: &[Node]) -> Option<Vec<Op>> {
+        let mut offset = 0;
+        let mut deltas: BTreeMap<isize, i64> = BTreeMap::new();
+        for node in body {
+            match node.op {
+                Op::Add(amount) => {
+                    let delta = deltas.entry(offset).or_insert(0);
+                    // Saturating adds in opposite directions do not commute.
+                    if self.overflow == Overflow::Saturate && delta.signum() * amount.signum() < 0 {
+                        return None;
+                    }
+                    *delta = self.add(*delta, amount);
+                }
+                Op::Move(step) => offset += step,
+                _ => return None,
+            }
+        }
+        if offset != 0 {
+            return None;
+        }
+
+        // With a counter step of -1 the loop runs `cell[0]` times; with +1 it
+        // runs `-cell[0]` times, which negates every factor. Counting up only
+        // reaches zero by wrapping.
+        let sign = match deltas.remove(&0) {
+            Some(-1) => 1,
+            Some(1) if self.overflow == Overflow::Wrap => -1,
+            _ => return None,
+        };
+        // A trap must stop inside the loop, at the add that overflows.
+        if self.overflow == Overflow::Error {
+            return None;
+        }
+        if deltas.values().all(|factor| *factor == 0) {
+            return None;
+        }
+
+        let mut ops: Vec<Op> = deltas
+            .into_iter()
+            .filter(|(_, factor)| *factor != 0)
+            .map(|(offset, factor)| Op::Mul {
+                offset,
+                factor: self.add(0, factor.wrapping_mul(sign)),
+            })
+            .collect();
+        ops.push(Op::Clear);
+        Some(ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimized(source: &str, level: u32) -> String {
+        let mut module = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        optimize(&mut module, &Passes::level(level), &Config::default());
+        module.to_string()
+    }
+
+    #[test]
+    fn level_zero_is_identity() {
+        assert_eq!(optimized("++[-]", 0), "add 1\nadd 1\nloop {\n  add -1\n}\n");
+    }
+
+    #[test]
+    fn fold_and_cancel() {
+        assert_eq!(optimized("+++>><+-.", 1), "add 3\nmove 1\noutput\n");
+        assert_eq!(optimized("+-<>", 1), "");
+    }
+
+    #[test]
+    fn fold_without_cancel() {
+        let mut module = Module::parse(&"++-".chars().collect::<Vec<_>>()).unwrap();
+        let mut passes = Passes::level(1);
+        passes.disable(Pass::Cancel);
+        optimize(&mut module, &passes, &Config::default());
+        assert_eq!(module.to_string(), "add 2\nadd -1\n");
+    }
+
+    #[test]
+    fn idioms() {
+        assert_eq!(optimized("[-]", 2), "clear\n");
+        assert_eq!(optimized("[<<]", 2), "scan -2\n");
+        assert_eq!(optimized("[->+>++<<]", 2), "mul 1, 1\nmul 2, 2\nclear\n");
+        assert_eq!(optimized("[>-<+]", 2), "mul 1, 1\nclear\n");
+        assert_eq!(optimized("[->+<<]", 2).lines().next(), Some("loop {"));
+    }
+
+    #[test]
+    fn idioms_need_level_two() {
+        assert_eq!(optimized("[-]", 1), "loop {\n  add -1\n}\n");
+    }
+
+    #[test]
+    fn cell_width_modulus() {
+        let source = format!("{}[{}>+<]", "+".repeat(256), "+".repeat(255));
+        assert_eq!(optimized(&source, 2), "mul 1, 1\nclear\n");
+
+        let mut module = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+        let config = Config {
+            cell_width: CellWidth::Bits16,
+            ..Config::default()
+        };
+        optimize(&mut module, &Passes::level(2), &config);
+        assert!(module
+            .to_string()
+            .starts_with("add 256\nloop {\n  add 255\n"));
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let optimized = |source: &str, overflow| {
+            let mut module = Module::parse(&source.chars().collect::<Vec<_>>()).unwrap();
+            let config = Config {
+                overflow,
+                ..Config::default()
+            };
+            optimize(&mut module, &Passes::level(2), &config);
+            module.to_string()
+        };
+        let source = format!("{}+-[+][-][->++<]", "+".repeat(256));
+        assert_eq!(
+            optimized(&source, Overflow::Saturate),
+            "add 257\nadd -1\nloop {\n  add 1\n}\nclear\nmul 1, 2\nclear\n"
+        );
+        let trapping = optimized(&source, Overflow::Error);
+        assert!(trapping.starts_with("add 1\nadd 1\n"));
+        assert!(trapping.ends_with("loop {\n  add 1\n}\nclear\nloop {\n  add -1\n  move 1\n  add 1\n  add 1\n  move -1\n}\n"));
+    }
+
+    #[test]
+    fn parse_pass_names() {
+        assert_eq!("scan".parse::<Pass>().unwrap(), Pass::Scan);
+        assert!("unroll".parse::<Pass>().is_err());
+    }
+}
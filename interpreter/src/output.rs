@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+
+use crate::config::TextMode;
+
+/// Writes the bytes produced by `.`, either verbatim or decoded for display.
+pub struct Output<'a, W: Write> {
+    stream: &'a mut W,
+    mode: Option<TextMode>,
+    /// An incomplete UTF-8 sequence waiting for its remaining bytes.
+    pending: Vec<u8>,
+}
+
+impl<'a, W: Write> Output<'a, W> {
+    pub fn new(stream: &'a mut W, mode: Option<TextMode>) -> Output<'a, W> {
+        Output {
+            stream,
+            mode,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Writes one cell value and returns the number of bytes written.
+    pub fn write(&mut self, byte: u8) -> io::Result<usize> {
+        match self.mode {
+            None => self.emit(&[byte]),
+            Some(TextMode::Latin1) => {
+                let mut buffer = [0; 2];
+                let encoded = char::from(byte).encode_utf8(&mut buffer);
+                self.emit(encoded.as_bytes())
+            }
+            Some(TextMode::Utf8) => {
+                self.pending.push(byte);
+                self.decode()
+            }
+        }
+    }
+
+    /// Flushes a trailing incomplete UTF-8 sequence as U+FFFD.
+    pub fn finish(&mut self) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        self.pending.clear();
+        self.emit(char::REPLACEMENT_CHARACTER.to_string().as_bytes())
+    }
+
+    fn decode(&mut self) -> io::Result<usize> {
+        let mut written = 0;
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    let text = text.to_owned();
+                    self.pending.clear();
+                    return Ok(written + self.emit(text.as_bytes())?);
+                }
+                // Wait for the rest of the sequence.
+                Err(err) if err.error_len().is_none() => return Ok(written),
+                Err(err) => {
+                    let invalid = err.error_len().unwrap_or(0);
+                    let valid: Vec<u8> =
+                        self.pending.drain(..err.valid_up_to() + invalid).collect();
+                    written += self.emit(&valid[..err.valid_up_to()])?;
+                    written += self.emit(char::REPLACEMENT_CHARACTER.to_string().as_bytes())?;
+                }
+            }
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.stream.write_all(bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(bytes: &[u8], mode: Option<TextMode>) -> Vec<u8> {
+        let mut stream = Vec::new();
+        let mut output = Output::new(&mut stream, mode);
+        for byte in bytes {
+            output.write(*byte).unwrap();
+        }
+        output.finish().unwrap();
+        stream
+    }
+
+    #[test]
+    fn bytes_are_verbatim() {
+        assert_eq!(render(&[0x41, 0xc8, 0xff], None), [0x41, 0xc8, 0xff]);
+    }
+
+    #[test]
+    fn latin1() {
+        assert_eq!(
+            render(&[0x41, 0xe9], Some(TextMode::Latin1)),
+            "Aé".as_bytes()
+        );
+    }
+
+    #[test]
+    fn utf8() {
+        let text = "h€llo";
+        assert_eq!(
+            render(text.as_bytes(), Some(TextMode::Utf8)),
+            text.as_bytes()
+        );
+        assert_eq!(
+            render(&[0x41, 0xff, 0x42, 0xe2, 0x82], Some(TextMode::Utf8)),
+            "A\u{fffd}B\u{fffd}".as_bytes()
+        );
+    }
+}
@@ -1,53 +1,91 @@
+use crate::{
+    error::ParseError,
+    ir::{check_brackets, commands, Position},
+};
+
+/// BF source loaded for the legacy engine: only the commands are kept, and
+/// every bracket is paired with its match once, at load time.
 pub struct Program {
-    content: Vec<char>,
+    commands: Vec<char>,
+    positions: Vec<Position>,
+    jumps: Vec<usize>,
     pub pointer: usize,
 }
 
 impl Program {
-    pub fn new(content: Vec<char>) -> Program {
-        Program {
-            content,
-            pointer: 0,
+    pub fn new(content: Vec<char>) -> Result<Program, ParseError> {
+        check_brackets(&content)?;
+
+        let (commands, positions): (Vec<char>, Vec<Position>) = commands(&content).unzip();
+        let mut jumps = vec![0; commands.len()];
+        let mut open = Vec::new();
+        for (index, command) in commands.iter().enumerate() {
+            match command {
+                '[' => open.push(index),
+                ']' => {
+                    let start = open.pop().expect("brackets are balanced");
+                    jumps[start] = index;
+                    jumps[index] = start;
+                }
+                _ => (),
+            }
         }
+
+        Ok(Program {
+            commands,
+            positions,
+            jumps,
+            pointer: 0,
+        })
     }
 
     pub fn finished(&self) -> bool {
-        self.pointer == self.content.len() as usize
+        self.pointer == self.commands.len()
     }
 
     pub fn forward(&mut self) {
         self.pointer += 1;
     }
 
-    pub fn back(&mut self) {
-        self.pointer -= 1;
+    pub fn command(&self) -> char {
+        self.commands[self.pointer]
     }
 
-    pub fn command(&self) -> char {
-        self.content[self.pointer]
+    pub fn position(&self) -> Position {
+        self.positions[self.pointer]
     }
 
-    pub fn fast_forward(&mut self, count: usize) {
-        if count != 0 {
-            self.forward();
+    /// The position of the last command, used once the program has finished.
+    pub fn end(&self) -> Position {
+        self.positions
+            .last()
+            .copied()
+            .unwrap_or(Position { line: 1, column: 1 })
+    }
 
-            match self.command() {
-                ']' => self.fast_forward(count - 1),
-                '[' => self.fast_forward(count + 1),
-                _ => self.fast_forward(count),
-            }
-        }
+    /// Moves to the bracket matching the current one.
+    pub fn jump(&mut self) {
+        self.pointer = self.jumps[self.pointer];
     }
+}
 
-    pub fn rewind(&mut self, count: usize) {
-        if count != 0 {
-            self.back();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            match self.command() {
-                '[' => self.rewind(count - 1),
-                ']' => self.rewind(count + 1),
-                _ => self.rewind(count),
-            }
-        }
+    #[test]
+    fn matched_jumps() {
+        let mut program = Program::new("a[[-]>]".chars().collect()).unwrap();
+        assert_eq!(program.command(), '[');
+        program.jump();
+        assert_eq!(program.pointer, 5);
+        program.jump();
+        assert_eq!(program.pointer, 0);
+    }
+
+    #[test]
+    fn unmatched() {
+        assert!(Program::new("[++]->]".chars().collect()).is_err());
+        assert!(Program::new("[[]".chars().collect()).is_err());
     }
 }
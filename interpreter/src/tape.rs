@@ -1,81 +1,388 @@
-use std::iter;
+use std::collections::HashMap;
 
-use text_io::read;
+use crate::config::{Boundary, Config, Overflow, TapeConfig};
 
-pub struct Tape {
-    content: Vec<u8>,
-    pointer: usize,
+/// A tape operation that was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The data pointer would move left of the first cell.
+    Underflow,
+    /// The data pointer would move right of the last cell.
+    Overflow,
+    /// A cell would leave its range under [`Overflow::Error`].
+    Cell { index: isize, previous: u64 },
 }
 
-impl Tape {
-    pub fn new() -> Tape {
+/// An unsigned integer type used as a tape cell. Arithmetic wraps modulo
+/// 2^bits; `,` and `.` move bytes through the low 8 bits.
+pub trait Cell: Copy + Eq + Default {
+    const MAX: Self;
+
+    /// Adds `amount` truncated to the cell width.
+    fn wrapping_add_i64(self, amount: i64) -> Self;
+    /// Adds `amount`, or returns `None` if the sum is out of range.
+    fn checked_add_i64(self, amount: i64) -> Option<Self>;
+    /// Adds `amount`, clamping the sum to the range of the cell.
+    fn saturating_add_i64(self, amount: i64) -> Self;
+    fn from_byte(byte: u8) -> Self;
+    fn low_byte(self) -> u8;
+    fn as_u64(self) -> u64;
+}
+
+macro_rules! cell {
+    ($($type:ty),*) => {
+        $(
+            impl Cell for $type {
+                const MAX: Self = <$type>::MAX;
+
+                fn wrapping_add_i64(self, amount: i64) -> Self {
+                    self.wrapping_add(amount as $type)
+                }
+
+                fn checked_add_i64(self, amount: i64) -> Option<Self> {
+                    <$type>::try_from(self as i128 + amount as i128).ok()
+                }
+
+                fn saturating_add_i64(self, amount: i64) -> Self {
+                    let sum = self as i128 + amount as i128;
+                    sum.clamp(0, <$type>::MAX as i128) as $type
+                }
+
+                fn from_byte(byte: u8) -> Self {
+                    byte as $type
+                }
+
+                fn low_byte(self) -> u8 {
+                    self as u8
+                }
+
+                fn as_u64(self) -> u64 {
+                    self as u64
+                }
+            }
+        )*
+    };
+}
+
+cell!(u8, u16, u32, u64);
+
+/// Number of cells in each page of a [`Boundary::Unbounded`] tape.
+const PAGE_SIZE: usize = 4096;
+
+/// Cells outside the dense part of an unbounded tape, allocated a page at a
+/// time when first written.
+struct Pages<C: Cell> {
+    pages: HashMap<isize, Box<[C]>>,
+}
+
+impl<C: Cell> Pages<C> {
+    fn locate(index: isize) -> (isize, usize) {
+        let size = PAGE_SIZE as isize;
+        (index.div_euclid(size), index.rem_euclid(size) as usize)
+    }
+
+    #[cold]
+    fn get(&self, index: isize) -> C {
+        let (page, slot) = Self::locate(index);
+        self.pages
+            .get(&page)
+            .map_or(C::default(), |cells| cells[slot])
+    }
+
+    #[cold]
+    fn get_mut(&mut self, index: isize) -> &mut C {
+        let (page, slot) = Self::locate(index);
+        let cells = self
+            .pages
+            .entry(page)
+            .or_insert_with(|| vec![C::default(); PAGE_SIZE].into_boxed_slice());
+        &mut cells[slot]
+    }
+
+    fn len(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+}
+
+/// The cells of a program. The first `length` cells are kept in a `Vec`;
+/// an unbounded tape keeps everything else in [`Pages`].
+pub struct Tape<C: Cell> {
+    content: Vec<C>,
+    pages: Pages<C>,
+    /// Index of the current cell in `content`, outside it only when the
+    /// tape is unbounded.
+    pointer: isize,
+    /// Where the starting cell is in `content`; cells added to the left
+    /// with [`Boundary::Grow`] move it right.
+    origin: usize,
+    config: TapeConfig,
+    overflow: Overflow,
+}
+
+impl<C: Cell> Tape<C> {
+    pub fn new(config: &Config) -> Tape<C> {
         Tape {
-            content: iter::repeat(0u8).take(30000).collect(),
+            content: vec![C::default(); config.tape.length.max(1)],
+            pages: Pages {
+                pages: HashMap::new(),
+            },
             pointer: 0,
+            origin: 0,
+            config: config.tape,
+            overflow: config.overflow,
         }
     }
 
-    pub fn inc(&mut self) {
-        let prev: u8 = *self.content.get_mut(self.pointer).unwrap();
-        let res;
-        match prev.checked_add(1) {
-            Some(r) => {
-                res = r;
+    pub fn inc(&mut self) -> Result<(), Fault> {
+        self.add(1)
+    }
+
+    pub fn dec(&mut self) -> Result<(), Fault> {
+        self.add(-1)
+    }
+
+    pub fn add(&mut self, amount: i64) -> Result<(), Fault> {
+        self.add_at(0, amount)
+    }
+
+    /// Adds `amount` to the cell `offset` cells from the pointer, following
+    /// the overflow policy.
+    pub fn add_at(&mut self, offset: isize, amount: i64) -> Result<(), Fault> {
+        let index = self.index(offset)?;
+        let origin = self.origin as isize;
+        let overflow = self.overflow;
+        let cell = self.cell_mut(index);
+        *cell = match overflow {
+            Overflow::Wrap => cell.wrapping_add_i64(amount),
+            Overflow::Saturate => cell.saturating_add_i64(amount),
+            Overflow::Error => cell.checked_add_i64(amount).ok_or(Fault::Cell {
+                index: index - origin,
+                previous: cell.as_u64(),
+            })?,
+        };
+        Ok(())
+    }
+
+    pub fn shift(&mut self, offset: isize) -> Result<(), Fault> {
+        self.pointer = self.index(offset)?;
+        Ok(())
+    }
+
+    /// The index in `content` of the cell `offset` cells from the pointer,
+    /// following the boundary policy.
+    fn index(&mut self, offset: isize) -> Result<isize, Fault> {
+        let length = self.content.len();
+        let target = match self.pointer.checked_add(offset) {
+            Some(target) => target,
+            None if offset < 0 => return Err(Fault::Underflow),
+            None => return Err(Fault::Overflow),
+        };
+        if (0..length as isize).contains(&target) {
+            return Ok(target);
+        }
+        match self.config.boundary {
+            Boundary::Error if target < 0 => Err(Fault::Underflow),
+            Boundary::Error => Err(Fault::Overflow),
+            Boundary::Wrap => Ok(target.rem_euclid(length as isize)),
+            Boundary::Grow if target < 0 => {
+                // Grow by at least the current length so repeated moves
+                // left take amortized constant time.
+                let added = target.unsigned_abs().max(length);
+                self.content
+                    .splice(0..0, std::iter::repeat_n(C::default(), added));
+                self.pointer += added as isize;
+                self.origin += added;
+                Ok(target + added as isize)
             }
-            None => {
-                res = 0;
+            Boundary::Grow => {
+                let needed = target as usize + 1;
+                self.content.resize(needed.max(length * 2), C::default());
+                Ok(target)
             }
+            Boundary::Unbounded => Ok(target),
         }
-        *self.content.get_mut(self.pointer).unwrap() = res;
     }
 
-    pub fn dec(&mut self) {
-        let prev: u8 = *self.content.get_mut(self.pointer).unwrap();
-        let res;
-        match prev.checked_sub(1) {
-            Some(r) => {
-                res = r;
-            }
-            None => {
-                res = u8::max_value();
-            }
+    fn cell_mut(&mut self, index: isize) -> &mut C {
+        // A negative index wraps to a huge `usize`, which also misses.
+        match self.content.get_mut(index as usize) {
+            Some(cell) => cell,
+            None => self.pages.get_mut(index),
         }
-        *self.content.get_mut(self.pointer).unwrap() = res;
     }
 
-    pub fn next(&mut self) {
-        self.pointer += 1;
+    pub fn next(&mut self) -> Result<(), Fault> {
+        self.shift(1)
     }
 
-    pub fn prev(&mut self) {
-        self.pointer -= 1;
+    pub fn prev(&mut self) -> Result<(), Fault> {
+        self.shift(-1)
     }
 
-    pub fn value(&self) -> u8 {
-        self.content[self.pointer]
+    pub fn value(&self) -> C {
+        match self.content.get(self.pointer as usize) {
+            Some(cell) => *cell,
+            None => self.pages.get(self.pointer),
+        }
     }
 
     pub fn zero(&self) -> bool {
-        self.value() == 0
+        self.value() == C::default()
     }
 
     pub fn not_zero(&self) -> bool {
         !self.zero()
     }
 
-    pub fn read_value(&mut self) {
-        let value: String;
-        value = read!("{}\n");
-        let res;
-        if value.len() == 0 {
-            res = self.value();
-        } else {
-            res = value.into_bytes()[0];
-        }
-        self.set_value(res)
+    pub fn set_value(&mut self, value: C) {
+        *self.cell_mut(self.pointer) = value;
+    }
+
+    /// The cells kept in one block, which is the whole tape unless it is
+    /// unbounded.
+    pub fn cells_mut(&mut self) -> &mut [C] {
+        &mut self.content
+    }
+
+    /// Bytes allocated for cells. Tapes never shrink, so this is also the
+    /// peak.
+    pub fn memory(&self) -> usize {
+        (self.content.capacity() + self.pages.len()) * std::mem::size_of::<C>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_cells_wrap_at_their_width() {
+        let mut tape: Tape<u16> = Tape::new(&Config::default());
+        tape.add(-1).unwrap();
+        assert_eq!(tape.value(), u16::MAX);
+        assert_eq!(tape.value().low_byte(), 0xff);
+        tape.add(257).unwrap();
+        assert_eq!(tape.value(), 256);
+        assert!(tape.not_zero());
+
+        let mut tape: Tape<u8> = Tape::new(&Config::default());
+        tape.add(256).unwrap();
+        assert!(tape.zero());
+    }
+
+    #[test]
+    fn overflow_policies() {
+        let mut tape: Tape<u8> = Tape::new(&Config {
+            overflow: Overflow::Saturate,
+            ..Config::default()
+        });
+        tape.dec().unwrap();
+        assert!(tape.zero());
+        tape.add(300).unwrap();
+        assert_eq!(tape.value(), u8::MAX);
+
+        let mut tape: Tape<u8> = Tape::new(&Config {
+            overflow: Overflow::Error,
+            ..Config::default()
+        });
+        tape.next().unwrap();
+        tape.add(255).unwrap();
+        assert_eq!(
+            tape.inc(),
+            Err(Fault::Cell {
+                index: 1,
+                previous: 255
+            })
+        );
+        assert_eq!(tape.value(), u8::MAX);
+        tape.prev().unwrap();
+        assert_eq!(
+            tape.dec(),
+            Err(Fault::Cell {
+                index: 0,
+                previous: 0
+            })
+        );
+    }
+
+    fn tape(length: usize, boundary: Boundary) -> Tape<u8> {
+        Tape::new(&Config {
+            tape: TapeConfig { length, boundary },
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn boundary_error() {
+        let mut tape = tape(2, Boundary::Error);
+        assert_eq!(tape.prev(), Err(Fault::Underflow));
+        tape.next().unwrap();
+        assert_eq!(tape.next(), Err(Fault::Overflow));
+        assert_eq!(tape.add_at(1, 1), Err(Fault::Overflow));
+    }
+
+    #[test]
+    fn boundary_wrap() {
+        let mut tape = tape(3, Boundary::Wrap);
+        tape.inc().unwrap();
+        tape.prev().unwrap();
+        tape.add(2).unwrap();
+        tape.shift(-5).unwrap();
+        assert_eq!(tape.value(), 1);
+        tape.add_at(7, 1).unwrap();
+        tape.shift(1).unwrap();
+        assert_eq!(tape.value(), 1);
+    }
+
+    #[test]
+    fn boundary_grow() {
+        let mut tape = tape(2, Boundary::Grow);
+        tape.inc().unwrap();
+        tape.shift(-3).unwrap();
+        tape.add(5).unwrap();
+        tape.shift(10).unwrap();
+        tape.add_at(-7, 1).unwrap();
+        tape.shift(-10).unwrap();
+        assert_eq!(tape.value(), 5);
+        tape.shift(3).unwrap();
+        assert_eq!(tape.value(), 2);
+
+        let mut tape: Tape<u8> = Tape::new(&Config {
+            tape: TapeConfig {
+                length: 1,
+                boundary: Boundary::Grow,
+            },
+            overflow: Overflow::Error,
+            ..Config::default()
+        });
+        tape.shift(-4).unwrap();
+        assert_eq!(
+            tape.dec(),
+            Err(Fault::Cell {
+                index: -4,
+                previous: 0
+            })
+        );
     }
 
-    fn set_value(&mut self, value: u8) {
-        *self.content.get_mut(self.pointer).unwrap() = value;
+    #[test]
+    fn boundary_unbounded() {
+        let mut tape = tape(16, Boundary::Unbounded);
+        assert_eq!(tape.memory(), 16);
+        tape.inc().unwrap();
+        tape.shift(-10).unwrap();
+        assert!(tape.zero());
+        assert_eq!(tape.memory(), 16);
+        tape.add(3).unwrap();
+        tape.shift(50_000_000).unwrap();
+        tape.add_at(-1, 4).unwrap();
+        assert_eq!(tape.memory(), 16 + 2 * PAGE_SIZE);
+        tape.shift(-1).unwrap();
+        assert_eq!(tape.value(), 4);
+        tape.shift(10 - 49_999_999).unwrap();
+        assert_eq!(tape.value(), 1);
+        tape.shift(-10).unwrap();
+        assert_eq!(tape.value(), 3);
+        assert_eq!(tape.shift(isize::MIN), Err(Fault::Underflow));
     }
 }
@@ -0,0 +1,41 @@
+// The sample programs with their input and expected output, as
+// `(name, source, input, output)`. The JIT and compiler tests `include!` this
+// table so that every engine and backend runs the same programs.
+[
+    (
+        "hello_world",
+        include_str!("hello_world.bf"),
+        b"",
+        include_bytes!("hello_world.bf.out"),
+    ),
+    (
+        "bottles",
+        include_str!("bottles.bf"),
+        b"",
+        include_bytes!("bottles.bf.out"),
+    ),
+    (
+        "factor",
+        include_str!("factor.bf"),
+        include_bytes!("factor.bf.in"),
+        include_bytes!("factor.bf.out"),
+    ),
+    (
+        "life",
+        include_str!("life.bf"),
+        include_bytes!("life.bf.in"),
+        include_bytes!("life.bf.out"),
+    ),
+    (
+        "mandelbrot",
+        include_str!("mandelbrot.bf"),
+        b"",
+        include_bytes!("mandelbrot.bf.out"),
+    ),
+    (
+        "bangbang",
+        include_str!("bangbang.bf"),
+        b"",
+        include_bytes!("bangbang.bf.out"),
+    ),
+]